@@ -0,0 +1,245 @@
+//! An assembler and disassembler for console programs (see `vm`).
+//!
+//! Assembly source is the day 8 input format, plus:
+//!
+//! - comments, from `#` or `;` to the end of the line
+//! - blank lines
+//! - any amount of whitespace between words
+//! - labels, defined by `name:` at the start of a line (before an
+//!   instruction, or on their own), and usable in place of a `jmp` or `nop`
+//!   offset:
+//!
+//! ```text
+//! start:
+//!     acc +1      ; count
+//!     jmp start
+//! ```
+
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::{anyhow, Context, Error};
+
+use crate::error::ParseError;
+use crate::vm::{is_label, parse_words, words, Program};
+
+/// Build the error for a problem at byte offset `column` of `line`.
+fn error(line_idx: usize, line: &str, column: usize, source: Error) -> ParseError {
+    let column = line[..column.min(line.len())].chars().count() + 1;
+    ParseError::new(8, line_idx + 1, column, line, source)
+}
+
+/// Assemble `source` into a program.
+pub fn assemble(source: &str) -> Result<Program, ParseError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    // Each instruction's line and words, to parse once every label is known.
+    let mut lines: Vec<(usize, Vec<(usize, &str)>)> = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let code = line.split(&['#', ';'][..]).next().unwrap_or("");
+        let mut words = words(code);
+        if let Some(&(offset, word)) = words.first() {
+            if let Some(name) = word.strip_suffix(':') {
+                if !is_label(name) {
+                    return Err(error(
+                        line_idx,
+                        line,
+                        offset,
+                        anyhow!("Invalid label: {}", name),
+                    ));
+                }
+                if labels.insert(name, lines.len()).is_some() {
+                    return Err(error(
+                        line_idx,
+                        line,
+                        offset,
+                        anyhow!("Duplicate label: {}", name),
+                    ));
+                }
+                words.remove(0);
+            }
+        }
+        if !words.is_empty() {
+            lines.push((line_idx, words));
+        }
+    }
+
+    let source_lines: Vec<&str> = source.lines().collect();
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(pc, (line_idx, words))| {
+            let label = |name: &str| {
+                labels
+                    .get(name)
+                    .map(|target| *target as i32 - pc as i32)
+                    .with_context(|| format!("Unknown label: {}", name))
+            };
+            parse_words(&words, &label)
+                .map_err(|e| error(line_idx, source_lines[line_idx], e.offset, e.into()))
+        })
+        .collect::<Result<_, _>>()
+        .map(Program::new)
+}
+
+/// Print `program` as assembly source, with a label (`L1`, `L2`, ...) at
+/// each jump target, used in place of the jump's offset.
+///
+/// Jumps out of the program, and `nop` arguments, are left as offsets.
+pub fn disassemble(program: &Program) -> String {
+    let instructions = program.instructions();
+    let target = |pc: usize| {
+        let offset = instructions[pc].jump_offset()?;
+        let target = pc as i64 + i64::from(offset);
+        if (0..=instructions.len() as i64).contains(&target) {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+    let mut targets: Vec<usize> = (0..instructions.len()).filter_map(target).collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let labels: HashMap<usize, String> = targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, target)| (target, format!("L{}", i + 1)))
+        .collect();
+
+    let mut out = String::new();
+    for pc in 0..=instructions.len() {
+        if let Some(label) = labels.get(&pc) {
+            writeln!(out, "{}:", label).unwrap();
+        }
+        let instruction = match instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        let mut words: Vec<String> = instruction
+            .operands()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        // The offset is always a jump's last operand.
+        if let Some(label) = target(pc).map(|target| &labels[&target]) {
+            *words.last_mut().unwrap() = label.clone();
+        }
+        words.insert(0, instruction.opcode().to_string());
+        writeln!(out, "    {}", words.join(" ")).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::vm::Instruction;
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble(
+            "# Count to three, then stop.
+start:  acc +1
+        jmp   next      ; skip the loop
+loop:
+        jmp loop
+next:   acc +2
+        jmp end
+        nop -100
+end:
+",
+        )
+        .unwrap();
+        assert_eq!(
+            program.instructions(),
+            &[
+                Instruction::Acc(1),
+                Instruction::Jmp(2),
+                Instruction::Jmp(0),
+                Instruction::Acc(2),
+                Instruction::Jmp(2),
+                Instruction::Nop(-100),
+            ]
+        );
+
+        let day8 = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+        assert_eq!(assemble(day8).unwrap(), day8.parse().unwrap());
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source: &str| assemble(source).unwrap_err().to_string();
+        assert_eq!(
+            error("acc +1\n  jmp nowhere"),
+            "day 8, line 2, column 7: Unknown label: nowhere
+  |
+2 |   jmp nowhere
+  |       ^"
+        );
+        assert!(error("a:\na: nop +0").starts_with("day 8, line 2, column 1: Duplicate label: a"));
+        assert!(error("1a: nop +0").contains("Invalid label: 1a"));
+        assert!(error("div +2").starts_with("day 8, line 1, column 1: Invalid instruction"));
+        assert!(error("acc").contains("No argument found!"));
+        assert!(error("acc +1 +2").starts_with("day 8, line 1, column 8: Unexpected"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = assemble(
+            "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6
+jmp +2
+jmp -100",
+        )
+        .unwrap();
+        let source = disassemble(&program);
+        assert_eq!(
+            source,
+            "    nop +0
+L1:
+    acc +1
+    jmp L3
+L2:
+    acc +3
+    jmp L1
+    acc -99
+L3:
+    acc +1
+    jmp L2
+    acc +6
+    jmp L4
+    jmp -100
+L4:
+"
+        );
+        assert_eq!(assemble(&source).unwrap(), program);
+    }
+
+    #[test]
+    fn test_extended_round_trip() {
+        let source = "    add r1 +3
+L1:
+    out r1
+    add r1 -1
+    jnz r1 L1
+    jz acc L2
+    mul acc r1
+L2:
+    hlt
+";
+        let program = assemble(source).unwrap();
+        assert_eq!(disassemble(&program), source.replace("acc", "r0"));
+        assert_eq!(assemble(&disassemble(&program)).unwrap(), program);
+        assert!(assemble("add r9 +1")
+            .unwrap_err()
+            .to_string()
+            .starts_with("day 8, line 1, column 5: Expected a register"));
+    }
+}
@@ -0,0 +1,58 @@
+//! Export day 7 bag rules as a GraphViz DOT digraph.
+//!
+//! Usage: `bags [--from COLOUR | --to COLOUR] [INPUT]`, reading from stdin if
+//! no input file is given and writing to stdout. `--from` restricts the graph
+//! to the bags a `COLOUR` bag eventually contains, and `--to` to the bags
+//! which can eventually contain one.
+
+use std::{
+    env, fs,
+    io::{self, Read},
+};
+
+use anyhow::{bail, Context, Result};
+
+use aoc_2020::{
+    day7::{Day7, Reach},
+    Solution,
+};
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut reach = Reach::All;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => {
+                reach = Reach::From(
+                    args.next()
+                        .context("--from requires a colour")?
+                        .as_str()
+                        .into(),
+                )
+            }
+            "--to" => {
+                reach = Reach::To(
+                    args.next()
+                        .context("--to requires a colour")?
+                        .as_str()
+                        .into(),
+                )
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+    let input = match path {
+        Some(path) => {
+            fs::read_to_string(&path).with_context(|| format!("Could not read {}", path))?
+        }
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    print!("{}", Day7::parse(&input)?.to_dot(&reach)?);
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Run a handheld console program (see `aoc_2020::vm`), such as a day 8
+//! input, written in assembly (see `aoc_2020::asm`).
+//!
+//! Usage: `console [--trace FILE] [--debug | --cfg | --analyze] PROGRAM`
+//!
+//! By default the program runs until it halts or is about to run an
+//! instruction a second time, then the accumulator and any output are
+//! printed. `--trace FILE` writes every instruction run to `FILE` (`-` for
+//! stdout). `--debug` starts an interactive debugger instead, reading
+//! commands from stdin; type `help` for a list.
+//!
+//! Without running the program, `--cfg` prints its control-flow graph as a
+//! GraphViz DOT digraph, and `--analyze` lists its unreachable instructions,
+//! the loops it can never leave, and the single-instruction patches which
+//! make it terminate.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+};
+
+use anyhow::{bail, Context, Result};
+
+use aoc_2020::{
+    asm::assemble,
+    cfg::ControlFlowGraph,
+    debugger::{Debugger, Response},
+    vm::{Machine, Program},
+};
+
+/// The maximum number of instructions to run outside the debugger.
+const BUDGET: usize = 10_000_000;
+
+fn debug(program: &Program) -> Result<()> {
+    let mut debugger = Debugger::new(program);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(vm) ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        match debugger.command(&line) {
+            Ok(Response::Output(output)) if output.is_empty() => {}
+            Ok(Response::Output(output)) => println!("{}", output),
+            Ok(Response::Quit) => return Ok(()),
+            Err(e) => println!("error: {:#}", e),
+        }
+    }
+}
+
+fn analyze(program: &Program) -> Result<()> {
+    let cfg = ControlFlowGraph::new(program);
+    let list = |items: Vec<String>| match items.len() {
+        0 => "none".to_string(),
+        _ => items.join(", "),
+    };
+    println!(
+        "unreachable: {}",
+        list(cfg.unreachable().iter().map(ToString::to_string).collect())
+    );
+    let loops = cfg.infinite_loops();
+    println!(
+        "infinite loops: {}",
+        list(loops.iter().map(|l| format!("{:?}", l)).collect())
+    );
+    println!(
+        "terminating patches: {}",
+        list(
+            cfg.terminating_patches()?
+                .iter()
+                .map(|p| format!("{}: {} (acc = {})", p.index, p.replacement, p.acc))
+                .collect()
+        )
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut trace = None;
+    let mut debug_mode = false;
+    let mut cfg = false;
+    let mut analyze_mode = false;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace = Some(args.next().context("--trace requires a file")?),
+            "--debug" => debug_mode = true,
+            "--cfg" => cfg = true,
+            "--analyze" => analyze_mode = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+    let path =
+        path.context("Usage: console [--trace FILE] [--debug | --cfg | --analyze] PROGRAM")?;
+    let source = fs::read_to_string(&path).with_context(|| format!("Could not read {}", path))?;
+    let program = assemble(&source)?;
+    if debug_mode {
+        return debug(&program);
+    }
+    if cfg {
+        print!("{}", ControlFlowGraph::new(&program).to_dot());
+        return Ok(());
+    }
+    if analyze_mode {
+        return analyze(&program);
+    }
+
+    let mut trace_out: Option<Box<dyn Write>> = match &trace {
+        Some(path) if path == "-" => Some(Box::new(io::stdout())),
+        Some(path) => Some(Box::new(io::BufWriter::new(
+            fs::File::create(path).with_context(|| format!("Could not create {}", path))?,
+        ))),
+        None => None,
+    };
+    // The first error writing the trace, which is reported once the program
+    // has stopped.
+    let mut trace_error = None;
+    let mut machine = Machine::new(&program);
+    if let Some(out) = &mut trace_out {
+        let error = &mut trace_error;
+        machine = machine.on_step(move |entry| {
+            if error.is_none() {
+                *error = writeln!(out, "{}", entry).err();
+            }
+        });
+    }
+    let outcome = machine.run(BUDGET);
+    let acc = machine.acc();
+    let output: Vec<String> = machine.output().iter().map(ToString::to_string).collect();
+    drop(machine);
+    if let Some(e) = trace_error {
+        return Err(e).context("Could not write the trace");
+    }
+    if let Some(out) = &mut trace_out {
+        out.flush().context("Could not write the trace")?;
+    }
+    println!("{:?}, acc = {}", outcome?, acc);
+    if !output.is_empty() {
+        println!("output: {}", output.join(","));
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+//! Export day 4 passports, with their validation results, to JSON Lines or CSV.
+//!
+//! Usage: `passports [--schema FILE] --export <jsonl|csv> [INPUT]`, reading
+//! from stdin if no input file is given and writing to stdout. Passports are
+//! checked against the YAML schema in `FILE` (see `Schema`) if given, or the
+//! puzzle's rules otherwise.
+
+use std::{
+    env, fs,
+    io::{self, Read},
+};
+
+use anyhow::{bail, Context, Result};
+
+use aoc_2020::{
+    day4::{export, parse_passports, ExportFormat, PASSPORT_SCHEMA},
+    schema::Schema,
+};
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut format = None;
+    let mut schema_path = None;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--export" => {
+                format = Some(
+                    args.next()
+                        .context("--export requires a format")?
+                        .parse::<ExportFormat>()?,
+                )
+            }
+            "--schema" => schema_path = Some(args.next().context("--schema requires a file")?),
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+    let format = format.context("Usage: passports [--schema FILE] --export <jsonl|csv> [INPUT]")?;
+    let loaded;
+    let schema = match schema_path {
+        Some(path) => {
+            loaded = Schema::load(path)?;
+            &loaded
+        }
+        None => &*PASSPORT_SCHEMA,
+    };
+    let input = match path {
+        Some(path) => {
+            fs::read_to_string(&path).with_context(|| format!("Could not read {}", path))?
+        }
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let passports = parse_passports(&input, schema);
+    export(&passports, schema, format, io::stdout().lock())
+}
@@ -0,0 +1,65 @@
+//! Draw a seat map of day 5 boarding passes.
+//!
+//! Usage: `seats [--svg FILE] [--aisle N]... [INPUT]`, reading from stdin if
+//! no input file is given and writing a text seat map to stdout. `--svg`
+//! also writes the map as an SVG image to `FILE`, and each `--aisle` adds an
+//! aisle after column `N` (counting from 0).
+
+use std::{
+    env, fs,
+    io::{self, Read},
+};
+
+use anyhow::{bail, Context, Result};
+
+use aoc_2020::{
+    day5::{Day5, Occupancy, PlaneLayout},
+    Solution,
+};
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut svg_path = None;
+    let mut aisles = Vec::new();
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--svg" => svg_path = Some(args.next().context("--svg requires a file")?),
+            "--aisle" => {
+                let column = args.next().context("--aisle requires a column")?;
+                aisles.push(
+                    column
+                        .parse::<u16>()
+                        .with_context(|| format!("Expected a column number, got {}", column))?,
+                );
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+    let input = match path {
+        Some(path) => {
+            fs::read_to_string(&path).with_context(|| format!("Could not read {}", path))?
+        }
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let layout = PlaneLayout::default();
+    if let Some(&column) = aisles.iter().find(|&&column| column >= layout.columns()) {
+        bail!(
+            "No column {} to put an aisle after (the plane has {} columns)",
+            column,
+            layout.columns()
+        );
+    }
+    let occupancy = Occupancy::new(&Day5::parse(&input)?, &layout);
+    if let Some(svg_path) = svg_path {
+        fs::write(&svg_path, occupancy.render_svg(&layout, &aisles))
+            .with_context(|| format!("Could not write {}", svg_path))?;
+    }
+    println!("{}", occupancy.render_text(&layout, &aisles));
+    Ok(())
+}
@@ -0,0 +1,60 @@
+//! Evaluate a set expression over the day 6 customs answers of each group.
+//!
+//! Usage: `survey <QUERY> [INPUT]`, printing the number of matching questions
+//! (and the questions themselves) for each group, then the total. See
+//! `aoc_2020::day6::Query` for the query syntax.
+//!
+//! `survey --totals [INPUT]` instead prints the number of people answering
+//! each question across every group.
+//!
+//! Input is read from stdin if no input file is given.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use anyhow::{Context, Result};
+
+use aoc_2020::day6::{question_totals, read_groups, universe, Group, Query};
+
+fn read_input(path: Option<String>) -> Result<Vec<Group>> {
+    let reader: Box<dyn BufRead> = match path {
+        Some(path) => Box::new(BufReader::new(
+            File::open(&path).with_context(|| format!("Could not read {}", path))?,
+        )),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    read_groups(reader).collect()
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let first = args
+        .next()
+        .context("Usage: survey <QUERY> [INPUT] | survey --totals [INPUT]")?;
+    if first == "--totals" {
+        let groups = read_input(args.next())?;
+        for (question, total) in question_totals(&groups) {
+            println!("{}\t{}", question, total);
+        }
+        return Ok(());
+    }
+    let query: Query = first.parse().context("Invalid query")?;
+    let groups = read_input(args.next())?;
+    let universe = universe(&groups);
+    let mut total = 0;
+    for (i, group) in groups.iter().enumerate() {
+        let questions = query.eval(group, &universe);
+        total += questions.len();
+        println!(
+            "{}\t{}\t{}",
+            i,
+            questions.len(),
+            questions.into_iter().collect::<String>()
+        );
+    }
+    println!("total\t{}", total);
+    Ok(())
+}
@@ -0,0 +1,597 @@
+//! Control-flow graphs and static analysis of console programs (see `vm`).
+
+use std::{collections::BTreeSet, convert::TryFrom};
+
+use anyhow::{Context, Result};
+
+use crate::vm::{Instruction, Operand, Program, Register};
+
+/// Where control can go after a basic block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// The start of another block, by index.
+    Block(usize),
+    /// The end of the program (or a `hlt`), so the program terminates.
+    Exit,
+    /// An address outside the program.
+    OutOfBounds(i64),
+}
+
+/// A run of instructions which always execute in order: only the first can
+/// be jumped to, and only the last can jump.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    /// The index of the first instruction.
+    pub start: usize,
+    /// The index just past the last instruction.
+    pub end: usize,
+    pub successors: Vec<Target>,
+}
+
+/// A patch making a program terminate: replacing a single instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Patch {
+    pub index: usize,
+    pub replacement: Instruction,
+    /// The accumulator once the patched program terminates.
+    pub acc: i64,
+}
+
+/// How much `instruction` adds to the accumulator, if that's the same
+/// whatever the registers hold, and it doesn't jump depending on them.
+fn acc_change(instruction: Instruction) -> Option<i64> {
+    match instruction {
+        Instruction::Acc(x) | Instruction::Add(Register::ACC, Operand::Immediate(x)) => {
+            Some(i64::from(x))
+        }
+        Instruction::Add(Register::ACC, _)
+        | Instruction::Mul(Register::ACC, _)
+        | Instruction::Jz(..)
+        | Instruction::Jnz(..) => None,
+        _ => Some(0),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph<'a> {
+    program: &'a Program,
+    blocks: Vec<BasicBlock>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let instructions = program.instructions();
+        let len = instructions.len();
+        let in_bounds = |pc: i64| (0..len as i64).contains(&pc);
+
+        // Blocks start at the beginning, at jump targets, and after jumps.
+        let mut leaders = BTreeSet::new();
+        if len > 0 {
+            leaders.insert(0);
+        }
+        for (pc, instruction) in instructions.iter().enumerate() {
+            let successors = instruction.successors(pc as i64);
+            if successors != [pc as i64 + 1] {
+                leaders.insert(pc + 1);
+                leaders.extend(
+                    successors
+                        .into_iter()
+                        .filter(|&target| in_bounds(target))
+                        .map(|target| target as usize),
+                );
+            }
+        }
+        leaders.remove(&len);
+
+        let starts: Vec<usize> = leaders.into_iter().collect();
+        let block_at = |pc: i64| {
+            if pc == len as i64 {
+                Target::Exit
+            } else if in_bounds(pc) {
+                Target::Block(starts.binary_search(&(pc as usize)).unwrap())
+            } else {
+                Target::OutOfBounds(pc)
+            }
+        };
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts.get(i + 1).copied().unwrap_or(len);
+                let last = end - 1;
+                let successors = match instructions[last] {
+                    Instruction::Hlt => vec![Target::Exit],
+                    instruction => instruction
+                        .successors(last as i64)
+                        .into_iter()
+                        .map(block_at)
+                        .collect(),
+                };
+                BasicBlock {
+                    start,
+                    end,
+                    successors,
+                }
+            })
+            .collect();
+        Self { program, blocks }
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Which blocks can be reached from the start of the program.
+    fn reachable_blocks(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![];
+        if !self.blocks.is_empty() {
+            reachable[0] = true;
+            stack.push(0);
+        }
+        while let Some(block) = stack.pop() {
+            for target in &self.blocks[block].successors {
+                if let Target::Block(next) = *target {
+                    if !reachable[next] {
+                        reachable[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Which blocks have some path out of the program, whether by
+    /// terminating or by jumping out of bounds.
+    fn escaping_blocks(&self) -> Vec<bool> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        let mut escaping = vec![false; self.blocks.len()];
+        let mut stack = vec![];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for target in &block.successors {
+                match *target {
+                    Target::Block(next) => predecessors[next].push(i),
+                    Target::Exit | Target::OutOfBounds(_) => {
+                        if !escaping[i] {
+                            escaping[i] = true;
+                            stack.push(i);
+                        }
+                    }
+                }
+            }
+        }
+        while let Some(block) = stack.pop() {
+            for &prev in &predecessors[block] {
+                if !escaping[prev] {
+                    escaping[prev] = true;
+                    stack.push(prev);
+                }
+            }
+        }
+        escaping
+    }
+
+    /// The instructions which can never run.
+    pub fn unreachable(&self) -> Vec<usize> {
+        self.reachable_blocks()
+            .into_iter()
+            .zip(&self.blocks)
+            .filter(|(reachable, _)| !reachable)
+            .flat_map(|(_, block)| block.start..block.end)
+            .collect()
+    }
+
+    /// Every reachable loop which, once entered, can never be left: each
+    /// given as its instructions, in order.
+    ///
+    /// The program runs forever whatever its registers hold if one of these
+    /// starts the program, or every path from the start leads to one.
+    pub fn infinite_loops(&self) -> Vec<Vec<usize>> {
+        let reachable = self.reachable_blocks();
+        let escaping = self.escaping_blocks();
+        let trapped: Vec<bool> = reachable
+            .iter()
+            .zip(&escaping)
+            .map(|(reachable, escaping)| *reachable && !escaping)
+            .collect();
+        let mut loops: Vec<Vec<usize>> = self
+            .strongly_connected_components(&trapped)
+            .into_iter()
+            .filter(|component| {
+                // A single block is only a loop if it jumps to itself.
+                component.len() > 1
+                    || self.blocks[component[0]]
+                        .successors
+                        .contains(&Target::Block(component[0]))
+            })
+            .map(|component| {
+                let mut instructions: Vec<usize> = component
+                    .into_iter()
+                    .flat_map(|block| self.blocks[block].start..self.blocks[block].end)
+                    .collect();
+                instructions.sort_unstable();
+                instructions
+            })
+            .collect();
+        loops.sort();
+        loops
+    }
+
+    /// Split the blocks marked in `include` into strongly connected
+    /// components, using Tarjan's algorithm with an explicit stack, so that
+    /// long chains of blocks can't overflow the call stack.
+    fn strongly_connected_components(&self, include: &[bool]) -> Vec<Vec<usize>> {
+        struct Tarjan<'g, 'a> {
+            graph: &'g ControlFlowGraph<'a>,
+            include: &'g [bool],
+            index: Vec<Option<usize>>,
+            low: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<usize>,
+            next_index: usize,
+            components: Vec<Vec<usize>>,
+        }
+
+        impl Tarjan<'_, '_> {
+            fn enter(&mut self, block: usize) {
+                self.index[block] = Some(self.next_index);
+                self.low[block] = self.next_index;
+                self.next_index += 1;
+                self.stack.push(block);
+                self.on_stack[block] = true;
+            }
+
+            fn visit(&mut self, root: usize) {
+                // The blocks being visited, deepest last, each with the
+                // number of its successors checked so far.
+                let mut visiting = vec![(root, 0)];
+                self.enter(root);
+                while let Some(&mut (block, ref mut checked)) = visiting.last_mut() {
+                    if let Some(target) = self.graph.blocks[block].successors.get(*checked) {
+                        *checked += 1;
+                        let next = match *target {
+                            Target::Block(next) if self.include[next] => next,
+                            _ => continue,
+                        };
+                        match self.index[next] {
+                            None => {
+                                self.enter(next);
+                                visiting.push((next, 0));
+                            }
+                            Some(index) if self.on_stack[next] => {
+                                self.low[block] = self.low[block].min(index);
+                            }
+                            Some(_) => {}
+                        }
+                        continue;
+                    }
+                    visiting.pop();
+                    if Some(self.low[block]) == self.index[block] {
+                        let mut component = vec![];
+                        while let Some(member) = self.stack.pop() {
+                            self.on_stack[member] = false;
+                            component.push(member);
+                            if member == block {
+                                break;
+                            }
+                        }
+                        self.components.push(component);
+                    }
+                    if let Some(&(parent, _)) = visiting.last() {
+                        self.low[parent] = self.low[parent].min(self.low[block]);
+                    }
+                }
+            }
+        }
+
+        let n = self.blocks.len();
+        let mut tarjan = Tarjan {
+            graph: self,
+            include,
+            index: vec![None; n],
+            low: vec![0; n],
+            on_stack: vec![false; n],
+            stack: vec![],
+            next_index: 0,
+            components: vec![],
+        };
+        for (block, included) in include.iter().enumerate() {
+            if *included && tarjan.index[block].is_none() {
+                tarjan.visit(block);
+            }
+        }
+        tarjan.components
+    }
+
+    /// Every swap of a single `nop` for a `jmp` (or vice versa) which makes
+    /// a program terminate, found without running it.
+    ///
+    /// If the program already terminates, there are no patches. Fails if
+    /// the program has conditional jumps, so that its path depends on its
+    /// registers, or changes the accumulator other than by adding constants
+    /// to it (with `acc`, or `add` with an immediate value).
+    pub fn terminating_patches(&self) -> Result<Vec<Patch>> {
+        let instructions = self.program.instructions();
+        let len = instructions.len();
+        let added = instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, &instruction)| {
+                acc_change(instruction).with_context(|| {
+                    format!(
+                        "Can't follow the accumulator without running instruction {} ({})",
+                        pc, instruction
+                    )
+                })
+            })
+            .collect::<Result<Vec<i64>>>()?;
+
+        // How much the accumulator increases by between each instruction
+        // and the end of the program, for the instructions from which the
+        // unpatched program terminates, found by walking backwards from the
+        // end and any `hlt`.
+        let mut predecessors = vec![vec![]; len + 1];
+        let mut to_end = vec![None; len + 1];
+        let mut stack = vec![len];
+        to_end[len] = Some(0);
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if *instruction == Instruction::Hlt {
+                to_end[pc] = Some(0);
+                stack.push(pc);
+            }
+            for target in instruction.successors(pc as i64) {
+                if (0..=len as i64).contains(&target) {
+                    predecessors[target as usize].push(pc);
+                }
+            }
+        }
+        while let Some(pc) = stack.pop() {
+            let acc = to_end[pc].unwrap();
+            for &prev in &predecessors[pc] {
+                if to_end[prev].is_none() {
+                    to_end[prev] = Some(added[prev].wrapping_add(acc));
+                    stack.push(prev);
+                }
+            }
+        }
+        if to_end.first().copied().flatten().is_some() {
+            return Ok(vec![]);
+        }
+
+        // Follow the unpatched program's path up to the first repeated
+        // instruction. Since the program doesn't terminate, none of the
+        // instructions it terminates from are on this path, so swapping an
+        // instruction on the path can't change where they lead.
+        let mut patches = vec![];
+        let mut seen = vec![false; len];
+        let mut acc: i64 = 0;
+        let mut pc = 0;
+        while (0..len as i64).contains(&pc) && !seen[pc as usize] {
+            let idx = pc as usize;
+            seen[idx] = true;
+            let mut replacement = instructions[idx];
+            replacement.swap_nop_jmp();
+            if replacement != instructions[idx] {
+                let target = replacement.successors(pc)[0];
+                if let Some(rest) = usize::try_from(target)
+                    .ok()
+                    .and_then(|target| to_end.get(target).copied().flatten())
+                {
+                    patches.push(Patch {
+                        index: idx,
+                        replacement,
+                        acc: acc.wrapping_add(rest),
+                    });
+                }
+            }
+            acc = acc.wrapping_add(added[idx]);
+            pc = instructions[idx].successors(pc)[0];
+        }
+        Ok(patches)
+    }
+
+    /// Render the graph as a GraphViz DOT digraph, with each block listing
+    /// its instructions. Unreachable blocks are dashed.
+    pub fn to_dot(&self) -> String {
+        let instructions = self.program.instructions();
+        let reachable = self.reachable_blocks();
+        let mut out = "digraph cfg {\n    node [shape=box, fontname=monospace];\n".to_string();
+        let mut out_of_bounds = BTreeSet::new();
+        let mut exits = false;
+        for (i, block) in self.blocks.iter().enumerate() {
+            let label: String = (block.start..block.end)
+                .map(|pc| format!("{}: {}\\l", pc, instructions[pc]))
+                .collect();
+            let style = if reachable[i] { "" } else { ", style=dashed" };
+            out.push_str(&format!("    b{} [label=\"{}\"{}];\n", i, label, style));
+            for target in &block.successors {
+                let node = match *target {
+                    Target::Block(next) => format!("b{}", next),
+                    Target::Exit => {
+                        exits = true;
+                        "exit".to_string()
+                    }
+                    Target::OutOfBounds(pc) => {
+                        out_of_bounds.insert(pc);
+                        format!("oob{}", pc.to_string().replace('-', "_"))
+                    }
+                };
+                out.push_str(&format!("    b{} -> {};\n", i, node));
+            }
+        }
+        if exits {
+            out.push_str("    exit [shape=doublecircle];\n");
+        }
+        for pc in out_of_bounds {
+            out.push_str(&format!(
+                "    oob{} [label=\"out of bounds: {}\", shape=octagon];\n",
+                pc.to_string().replace('-', "_"),
+                pc
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const PROGRAM: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn program(s: &str) -> Program {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let program = program(PROGRAM);
+        let cfg = ControlFlowGraph::new(&program);
+        let blocks: Vec<_> = cfg
+            .blocks()
+            .iter()
+            .map(|block| (block.start, block.end, block.successors.clone()))
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (0, 1, vec![Target::Block(1)]),
+                (1, 3, vec![Target::Block(4)]),
+                (3, 5, vec![Target::Block(1)]),
+                (5, 6, vec![Target::Block(4)]),
+                (6, 8, vec![Target::Block(2)]),
+                (8, 9, vec![Target::Exit]),
+            ]
+        );
+        assert!(ControlFlowGraph::new(&Program::default())
+            .blocks()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_analysis() {
+        let program = program(PROGRAM);
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.unreachable(), vec![5, 8]);
+        assert_eq!(cfg.infinite_loops(), vec![vec![1, 2, 3, 4, 6, 7]]);
+        assert_eq!(
+            cfg.terminating_patches().unwrap(),
+            vec![Patch {
+                index: 7,
+                replacement: Instruction::Nop(-4),
+                acc: 8
+            }]
+        );
+
+        // Terminates, so there's nothing to patch.
+        let program = self::program("acc +1\njmp +2\njmp -2\nhlt\nacc +5");
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.unreachable(), vec![2, 4]);
+        assert!(cfg.infinite_loops().is_empty());
+        assert!(cfg.terminating_patches().unwrap().is_empty());
+
+        // Either instruction in the loop can be patched.
+        let program = self::program("acc +2\nnop +3\njmp -1\nacc +1\nacc +4");
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.infinite_loops(), vec![vec![1, 2]]);
+        assert_eq!(
+            cfg.terminating_patches().unwrap(),
+            vec![
+                Patch {
+                    index: 1,
+                    replacement: Instruction::Jmp(3),
+                    acc: 6
+                },
+                Patch {
+                    index: 2,
+                    replacement: Instruction::Nop(-1),
+                    acc: 7
+                },
+            ]
+        );
+
+        // Jumping out of bounds doesn't count as terminating.
+        let program = self::program("jmp +0\njmp -5");
+        assert!(ControlFlowGraph::new(&program)
+            .terminating_patches()
+            .unwrap()
+            .is_empty());
+
+        // Adding a constant to r0 counts, as do other registers' arithmetic
+        // and output, but not anything which depends on the registers.
+        let program = self::program("add r0 +5\nmul r1 r0\nout r1\nnop +0\njmp -1");
+        assert_eq!(
+            ControlFlowGraph::new(&program)
+                .terminating_patches()
+                .unwrap(),
+            vec![Patch {
+                index: 4,
+                replacement: Instruction::Nop(-1),
+                acc: 5
+            }]
+        );
+        for source in &["mul r0 +2\njmp +0", "add r0 r1\njmp +0", "jz r1 +2\njmp +0"] {
+            let program = self::program(source);
+            let err = ControlFlowGraph::new(&program)
+                .terminating_patches()
+                .unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with("Can't follow the accumulator without running instruction 0"),
+                "{}",
+                source
+            );
+        }
+
+        // A loop which can exit, but only by jumping out of bounds, isn't
+        // guaranteed to run forever.
+        let program = self::program("jz r1 -5\njmp -1");
+        assert!(ControlFlowGraph::new(&program).infinite_loops().is_empty());
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // Every instruction is its own block: the first jumps to the last,
+        // and each of the others jumps back to the one before it.
+        let n = 100_000;
+        let mut source = format!("jmp +{}\n", n - 1);
+        source.push_str(&"jmp -1\n".repeat(n - 1));
+        let program = program(&source);
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.blocks().len(), n);
+        assert_eq!(cfg.infinite_loops(), vec![(0..n).collect::<Vec<_>>()]);
+        assert_eq!(cfg.terminating_patches().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let program = program("acc +1\njmp +2\njmp -8\nhlt");
+        let dot = ControlFlowGraph::new(&program).to_dot();
+        assert_eq!(
+            dot,
+            r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    b0 [label="0: acc +1\l1: jmp +2\l"];
+    b0 -> b2;
+    b1 [label="2: jmp -8\l", style=dashed];
+    b1 -> oob_6;
+    b2 [label="3: hlt\l"];
+    b2 -> exit;
+    exit [shape=doublecircle];
+    oob_6 [label="out of bounds: -6", shape=octagon];
+}
+"#
+        );
+    }
+}
@@ -1,46 +1,224 @@
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ParseError};
+use crate::solution::Solution;
+
 #[aoc_generator(day1)]
-fn parse_input(input: &str) -> Vec<u32> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<u32>, ParseError> {
+    parse_lines(1, input)
+}
+
+/// Find `k` distinct expenses summing to `target`.
+///
+/// Returns the `(index, value)` pairs of the first matching combination found,
+/// ordered by value, where `index` is the position of the expense in
+/// `expenses`. See `k_sums` for details.
+pub fn k_sum(expenses: &[u32], k: usize, target: u32) -> Option<Vec<(usize, u32)>> {
+    k_sums(expenses, k, target).next()
+}
+
+/// Like `k_sum`, but returns an error rather than `None` if no combination of
+/// expenses sums to `target`.
+pub fn find_k_sum(expenses: &[u32], k: usize, target: u32) -> Result<Vec<(usize, u32)>> {
+    k_sum(expenses, k, target)
+        .with_context(|| format!("No combination of {} expenses sums to {}", k, target))
+}
+
+/// Iterate over every combination of `k` distinct expenses summing to `target`.
+///
+/// Each expense is used at most once per combination, but repeated values are
+/// treated as separate expenses: `[1010, 1010, 1010]` contains three pairs
+/// summing to 2020. Combinations are yielded as `(index, value)` pairs ordered
+/// by value.
+///
+/// The expenses are sorted once, then the first `k - 2` entries of each
+/// combination are enumerated and the remaining pair is found with two
+/// pointers, so finding every combination takes O(n^(k-1)) time (O(n²) for
+/// `k = 3`).
+pub fn k_sums(expenses: &[u32], k: usize, target: u32) -> KSums {
+    let mut sorted: Vec<_> = expenses.iter().copied().enumerate().collect();
+    sorted.sort_unstable_by_key(|&(_, value)| value);
+    let tail = k.min(2);
+    KSums {
+        done: k > sorted.len(),
+        sorted,
+        target: target.into(),
+        prefix: (0..k - tail).collect(),
+        tail,
+        pending: VecDeque::new(),
+    }
+}
+
+/// Iterator returned by `k_sums`.
+#[derive(Debug)]
+pub struct KSums {
+    /// `(index, value)` pairs sorted by value.
+    sorted: Vec<(usize, u32)>,
+    target: u64,
+    /// Positions in `sorted` of the entries currently fixed before the tail.
+    prefix: Vec<usize>,
+    /// Number of entries to search for after the prefix (at most 2).
+    tail: usize,
+    /// Combinations found for the current prefix which haven't been yielded.
+    pending: VecDeque<Vec<(usize, u32)>>,
+    done: bool,
 }
 
-fn find_double_2020_expenses(expenses: &[u32]) -> [u32; 2] {
-    let mut v = expenses.to_vec();
-    v.sort_unstable();
-    for x in &v {
-        for y in v.iter().rev() {
-            if x + y == 2020 {
-                return [*x, *y];
+impl KSums {
+    /// Find every tail for the current prefix, adding the completed
+    /// combinations to `pending`.
+    fn find_tails(&mut self, remaining: u64) {
+        let start = self.prefix.last().map_or(0, |p| p + 1);
+        let candidates = &self.sorted[start..];
+        let mut tails = Vec::new();
+        match self.tail {
+            0 => {
+                if remaining == 0 {
+                    tails.push(vec![]);
+                }
+            }
+            1 => {
+                let from = candidates.partition_point(|&(_, v)| u64::from(v) < remaining);
+                let to = candidates.partition_point(|&(_, v)| u64::from(v) <= remaining);
+                tails.extend(candidates[from..to].iter().map(|&entry| vec![entry]));
+            }
+            _ => {
+                let (mut lo, mut hi) = (0, candidates.len().saturating_sub(1));
+                while lo < hi {
+                    let (low, high) = (candidates[lo].1, candidates[hi].1);
+                    let sum = u64::from(low) + u64::from(high);
+                    if sum < remaining {
+                        lo += 1;
+                    } else if sum > remaining {
+                        hi -= 1;
+                    } else if low == high {
+                        // Every pair from this run of equal values matches.
+                        for i in lo..hi {
+                            for j in i + 1..=hi {
+                                tails.push(vec![candidates[i], candidates[j]]);
+                            }
+                        }
+                        break;
+                    } else {
+                        // Pair up every copy of the low value with every copy
+                        // of the high value.
+                        let low_end =
+                            lo + candidates[lo..].iter().take_while(|e| e.1 == low).count();
+                        let high_start = hi + 1
+                            - candidates[..=hi]
+                                .iter()
+                                .rev()
+                                .take_while(|e| e.1 == high)
+                                .count();
+                        for i in lo..low_end {
+                            for j in high_start..=hi {
+                                tails.push(vec![candidates[i], candidates[j]]);
+                            }
+                        }
+                        lo = low_end;
+                        hi = high_start - 1;
+                    }
+                }
             }
         }
+        for tail in tails {
+            let mut combination: Vec<_> = self.prefix.iter().map(|&p| self.sorted[p]).collect();
+            combination.extend(tail);
+            self.pending.push_back(combination);
+        }
     }
-    unreachable!()
-}
 
-fn find_triple_2020_expenses(expenses: &[u32]) -> [u32; 3] {
-    let mut v = expenses.to_vec();
-    v.sort_unstable();
-    for x in &v {
-        for y in v.iter().rev() {
-            for z in &v {
-                if x + y + z == 2020 {
-                    return [*x, *y, *z];
+    /// Move on to the next prefix by incrementing the position at `level`
+    /// (or an earlier one, if that position can't be incremented any further).
+    fn advance(&mut self, level: Option<usize>) {
+        let n = self.sorted.len();
+        let m = self.prefix.len();
+        let mut level = level;
+        while let Some(l) = level {
+            // Leave room for the rest of the prefix and the tail.
+            if self.prefix[l] + 1 + (m - l) + self.tail <= n {
+                self.prefix[l] += 1;
+                for j in l + 1..m {
+                    self.prefix[j] = self.prefix[j - 1] + 1;
                 }
+                return;
             }
+            level = l.checked_sub(1);
         }
+        self.done = true;
     }
-    unreachable!()
+}
+
+impl Iterator for KSums {
+    type Item = Vec<(usize, u32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(combination) = self.pending.pop_front() {
+                return Some(combination);
+            }
+            if self.done {
+                return None;
+            }
+            let prefix_sum: u64 = self
+                .prefix
+                .iter()
+                .map(|&p| u64::from(self.sorted[p].1))
+                .sum();
+            let start = self.prefix.last().map_or(0, |p| p + 1);
+            let smallest_tail: u64 = self.sorted[start..start + self.tail]
+                .iter()
+                .map(|&(_, v)| u64::from(v))
+                .sum();
+            let last = self.prefix.len().checked_sub(1);
+            if prefix_sum + smallest_tail > self.target {
+                // Values only increase from here, so moving the last prefix
+                // entry along won't help; move the one before it instead.
+                self.advance(last.and_then(|l| l.checked_sub(1)));
+            } else {
+                self.find_tails(self.target - prefix_sum);
+                self.advance(last);
+            }
+        }
+    }
+}
+
+fn product(entries: &[(usize, u32)]) -> u32 {
+    entries.iter().map(|&(_, value)| value).product()
 }
 
 #[aoc(day1, part1)]
-fn part1(input: &[u32]) -> u32 {
-    find_double_2020_expenses(input).iter().product()
+fn part1(input: &[u32]) -> Result<u32> {
+    find_k_sum(input, 2, 2020).map(|entries| product(&entries))
 }
 
 #[aoc(day1, part2)]
-fn part2(input: &[u32]) -> u32 {
-    find_triple_2020_expenses(input).iter().product()
+fn part2(input: &[u32]) -> Result<u32> {
+    find_k_sum(input, 3, 2020).map(|entries| product(&entries))
+}
+
+pub struct Day1;
+
+impl Solution for Day1 {
+    const DAY: u8 = 1;
+    type Input = Vec<u32>;
+    type Output = u32;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -48,23 +226,71 @@ mod tests {
 
     use super::*;
 
+    const INPUT: &[u32] = &[1721, 979, 366, 299, 675, 1456];
+
     #[test]
     fn test_part1() {
+        assert_eq!(part1(INPUT).unwrap(), 514579u32);
+    }
+
+    #[test]
+    fn test_part2() {
+        assert_eq!(part2(INPUT).unwrap(), 241861950u32);
+    }
+
+    #[test]
+    fn test_k_sum() {
+        assert_eq!(k_sum(INPUT, 2, 2020), Some(vec![(3, 299), (0, 1721)]));
         assert_eq!(
-            find_double_2020_expenses(&vec![1721, 979, 366, 299, 675, 1456])
-                .iter()
-                .product::<u32>(),
-            514579u32
+            k_sum(INPUT, 3, 2020),
+            Some(vec![(2, 366), (4, 675), (1, 979)])
+        );
+        assert_eq!(
+            k_sum(INPUT, 4, 299 + 366 + 675 + 1721),
+            Some(vec![(3, 299), (2, 366), (4, 675), (0, 1721)])
         );
+        assert_eq!(
+            k_sum(INPUT, 5, 4040),
+            Some(vec![(3, 299), (2, 366), (4, 675), (1, 979), (0, 1721)])
+        );
+        assert_eq!(k_sum(INPUT, 1, 675), Some(vec![(4, 675)]));
+        assert_eq!(k_sum(INPUT, 2, 1), None);
+        assert_eq!(k_sum(INPUT, 7, 2020), None);
     }
 
     #[test]
-    fn test_part2() {
+    fn test_k_sums() {
         assert_eq!(
-            find_triple_2020_expenses(&vec![1721, 979, 366, 299, 675, 1456])
-                .iter()
-                .product::<u32>(),
-            241861950u32
+            k_sums(&[1000, 1020, 1010, 1000, 1020], 2, 2020).collect::<Vec<_>>(),
+            vec![
+                vec![(0, 1000), (1, 1020)],
+                vec![(0, 1000), (4, 1020)],
+                vec![(3, 1000), (1, 1020)],
+                vec![(3, 1000), (4, 1020)],
+            ]
         );
+        assert_eq!(
+            k_sums(&[1010, 5, 1010, 1010], 2, 2020).count(),
+            3,
+            "each pair of 1010s should be found exactly once"
+        );
+        assert_eq!(
+            k_sums(&[1, 2, 3, 4, 5, 6], 3, 10).collect::<Vec<_>>(),
+            vec![
+                vec![(0, 1), (2, 3), (5, 6)],
+                vec![(0, 1), (3, 4), (4, 5)],
+                vec![(1, 2), (2, 3), (4, 5)],
+            ]
+        );
+        assert_eq!(k_sums(&[1, 1, 1, 1], 4, 4).count(), 1);
+        assert_eq!(k_sums(&[1, 1, 1, 1], 3, 3).count(), 4);
+    }
+
+    #[test]
+    fn test_no_combination() {
+        // A single 1010 mustn't be paired with itself.
+        assert_eq!(k_sum(&[1010, 1, 2], 2, 2020), None);
+        assert!(find_k_sum(&[1010, 1, 2], 2, 2020).is_err());
+        assert!(part1(&[1, 2, 3]).is_err());
     }
 }
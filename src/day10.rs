@@ -1,21 +1,50 @@
 use std::collections::HashMap;
 
+use anyhow::{bail, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ParseError};
+use crate::solution::Solution;
+
 #[aoc_generator(day10)]
-fn parse_input(input: &str) -> Vec<usize> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<usize>, ParseError> {
+    parse_lines(10, input)
+}
+
+/// The joltages from the outlet (0) up through every adapter, in order.
+///
+/// Fails unless every adapter can be chained: they must be distinct and
+/// (starting from the outlet) at most 3 jolts apart.
+fn chain(input: &[usize]) -> Result<Vec<usize>> {
+    if input.is_empty() {
+        bail!("No adapters");
+    }
+    let mut chain = input.to_vec();
+    chain.push(0);
+    chain.sort_unstable();
+    for pair in chain.windows(2) {
+        match pair[1] - pair[0] {
+            0 => bail!(
+                "More than one adapter (or the outlet) rated {} jolts",
+                pair[0]
+            ),
+            1..=3 => {}
+            _ => bail!("No adapter between {} and {} jolts", pair[0], pair[1]),
+        }
+    }
+    Ok(chain)
 }
 
 #[aoc(day10, part1)]
-fn part1(input: &[usize]) -> usize {
-    let mut input = input.to_vec();
-    input.sort_unstable();
-    let result = input.windows(2).fold(vec![1; 3], |mut acc, x| {
+fn part1(input: &[usize]) -> Result<usize> {
+    let mut chain = chain(input)?;
+    // The device's built-in adapter is always 3 jolts above the highest.
+    chain.push(chain[chain.len() - 1] + 3);
+    let result = chain.windows(2).fold(vec![0; 3], |mut acc, x| {
         acc[x[1] - x[0] - 1] += 1;
         acc
     });
-    result[0] * result[2]
+    Ok(result[0] * result[2])
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -30,7 +59,7 @@ struct Node {
 type Graph = HashMap<NodeId, Node>;
 
 impl Node {
-    fn paths(&self, graph: &Graph, mut cache: &mut HashMap<NodeId, usize>) -> usize {
+    fn paths(&self, graph: &Graph, cache: &mut HashMap<NodeId, usize>) -> usize {
         let mut sum = 0;
         if self.children.iter().all(Option::is_none) {
             sum += 1;
@@ -39,7 +68,7 @@ impl Node {
                 if let Some(cached) = cache.get(child) {
                     sum += cached;
                 } else {
-                    sum += graph[child].paths(&graph, &mut cache);
+                    sum += graph[child].paths(graph, cache);
                 }
             }
         }
@@ -87,7 +116,7 @@ fn insert_last(graph: &mut Graph, window: &[usize]) {
 }
 
 #[aoc(day10, part2)]
-fn part2(input: &[usize]) -> usize {
+fn part2(input: &[usize]) -> Result<usize> {
     // Represent adapters as nodes in graph; count number of paths from
     // start to finish. Can then use DFS with memoization.
     // Turns out there's a much sneakier way to do it, from
@@ -112,9 +141,7 @@ fn part2(input: &[usize]) -> usize {
     // The above solution runs in 350 ns so is much much faster!
 
     // Collect, add the initial node, sort, then add the last node.
-    let mut input = input.to_vec();
-    input.push(0);
-    input.sort_unstable();
+    let mut input = chain(input)?;
     input.push(input[input.len() - 1] + 3);
 
     // Create the graph by iterating over windows of length 4.
@@ -138,7 +165,27 @@ fn part2(input: &[usize]) -> usize {
     insert_last(&mut graph, last);
 
     let mut cache = HashMap::with_capacity(graph.len());
-    graph[&NodeId(0)].paths(&graph, &mut cache)
+    Ok(graph[&NodeId(0)].paths(&graph, &mut cache))
+}
+
+pub struct Day10;
+
+impl Solution for Day10 {
+    const DAY: u8 = 10;
+    type Input = Vec<usize>;
+    type Output = usize;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -149,8 +196,9 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "16
+            part1(
+                &parse_input(
+                    "16
 10
 15
 5
@@ -161,12 +209,16 @@ mod tests {
 6
 12
 4"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             7 * 5
         );
         assert_eq!(
-            part1(&parse_input(
-                "28
+            part1(
+                &parse_input(
+                    "28
 33
 18
 42
@@ -197,7 +249,10 @@ mod tests {
 34
 10
 3"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             22 * 10
         );
     }
@@ -205,8 +260,9 @@ mod tests {
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "16
+            part2(
+                &parse_input(
+                    "16
 10
 15
 5
@@ -217,12 +273,16 @@ mod tests {
 6
 12
 4"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             8
         );
         assert_eq!(
-            part2(&parse_input(
-                "28
+            part2(
+                &parse_input(
+                    "28
 33
 18
 42
@@ -253,8 +313,27 @@ mod tests {
 34
 10
 3"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             19208
         );
     }
+
+    #[test]
+    fn test_invalid_adapters() {
+        assert_eq!(part1(&[]).unwrap_err().to_string(), "No adapters");
+        assert_eq!(
+            part1(&[1, 1, 2]).unwrap_err().to_string(),
+            "More than one adapter (or the outlet) rated 1 jolts"
+        );
+        assert_eq!(
+            part2(&[1, 10]).unwrap_err().to_string(),
+            "No adapter between 1 and 10 jolts"
+        );
+        assert!(part2(&[4]).is_err());
+        assert!(part1(&[0, 1]).is_err());
+        assert_eq!(part2(&[3]).unwrap(), 1);
+    }
 }
@@ -1,12 +1,15 @@
 use std::str::FromStr;
 
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use serde::Deserialize;
 use serde_scan::scan;
 
+use crate::error::{parse_lines, ParseError};
+use crate::solution::Solution;
+
 #[derive(Deserialize)]
-struct Password {
+pub struct Password {
     min: usize,
     max: usize,
     letter: char,
@@ -18,18 +21,14 @@ impl Password {
         let count = self.password.matches(self.letter).count();
         count >= self.min && count <= self.max
     }
-    fn is_actually_valid(&self) -> bool {
-        let first = self
-            .password
-            .chars()
-            .nth(self.min - 1)
-            .map_or(false, |x| x == self.letter);
-        let second = self
-            .password
-            .chars()
-            .nth(self.max - 1)
-            .map_or(false, |x| x == self.letter);
-        first ^ second
+    fn is_actually_valid(&self) -> Result<bool> {
+        let has_letter = |position: usize| -> Result<bool> {
+            let index = position
+                .checked_sub(1)
+                .context("Positions start at 1, not 0")?;
+            Ok(self.password.chars().nth(index) == Some(self.letter))
+        };
+        Ok(has_letter(self.min)? ^ has_letter(self.max)?)
     }
 }
 
@@ -41,8 +40,8 @@ impl FromStr for Password {
 }
 
 #[aoc_generator(day2)]
-fn parse_input(input: &str) -> Vec<Password> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<Password>, ParseError> {
+    parse_lines(2, input)
 }
 
 #[aoc(day2, part1)]
@@ -54,17 +53,34 @@ fn part1(input: &[Password]) -> u32 {
 }
 
 #[aoc(day2, part2)]
-fn part2(input: &[Password]) -> u32 {
-    input
-        .iter()
-        .filter_map(|x| {
-            if Password::is_actually_valid(x) {
-                Some(1)
-            } else {
-                None
-            }
-        })
-        .sum()
+fn part2(input: &[Password]) -> Result<u32> {
+    let mut valid = 0;
+    for password in input {
+        if password.is_actually_valid()? {
+            valid += 1;
+        }
+    }
+    Ok(valid)
+}
+
+pub struct Day2;
+
+impl Solution for Day2 {
+    const DAY: u8 = 2;
+    type Input = Vec<Password>;
+    type Output = u32;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part1(input))
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -75,11 +91,14 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "1-3 a: abcde
+            part1(
+                &parse_input(
+                    "1-3 a: abcde
 1-3 b: cdefg
 2-9 c: ccccccccc"
-            )),
+                )
+                .unwrap()
+            ),
             2
         );
     }
@@ -87,12 +106,17 @@ mod tests {
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "1-3 a: abcde
+            part2(
+                &parse_input(
+                    "1-3 a: abcde
 1-3 b: cdefg
 2-9 c: ccccccccc"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             1
         );
+        assert!(part2(&parse_input("0-3 a: abc").unwrap()).is_err());
     }
 }
@@ -1,26 +1,30 @@
-use std::{convert::Infallible, ops::Deref, str::FromStr};
+use std::{convert::TryFrom, ops::Deref, str::FromStr};
 
-// use anyhow::{Error, Result};
+use anyhow::{anyhow, bail, Error, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ColumnError, ParseError};
+use crate::solution::Solution;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
-enum Coordinate {
+pub enum Coordinate {
     Open,
     Tree,
 }
 
-impl From<char> for Coordinate {
-    fn from(c: char) -> Self {
+impl TryFrom<char> for Coordinate {
+    type Error = Error;
+    fn try_from(c: char) -> Result<Self> {
         match c {
-            '#' => Self::Tree,
-            '.' => Self::Open,
-            _ => panic!("Unexpected coordinate! {}", c),
+            '#' => Ok(Self::Tree),
+            '.' => Ok(Self::Open),
+            _ => Err(anyhow!("Unexpected coordinate! {}", c)),
         }
     }
 }
 
 #[derive(Debug)]
-struct Row(Vec<Coordinate>);
+pub struct Row(Vec<Coordinate>);
 
 impl Deref for Row {
     type Target = Vec<Coordinate>;
@@ -30,21 +34,36 @@ impl Deref for Row {
 }
 
 impl FromStr for Row {
-    type Err = Infallible;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.chars().map(Coordinate::from).collect()))
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(
+            s.char_indices()
+                .map(|(i, c)| Coordinate::try_from(c).map_err(|e| ColumnError::new(i, e)))
+                .collect::<Result<_, _>>()?,
+        ))
     }
 }
 
 #[aoc_generator(day3)]
-fn parse_input(input: &str) -> Vec<Row> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<Row>, ParseError> {
+    parse_lines(3, input)
 }
 
-fn n_trees(rows: &[Row], trajectory: &(usize, usize)) -> usize {
+fn n_trees(rows: &[Row], trajectory: &(usize, usize)) -> Result<usize> {
+    let width = match rows.first() {
+        Some(row) if !row.is_empty() => row.len(),
+        _ => bail!("The map is empty"),
+    };
+    if let Some(i) = rows.iter().position(|row| row.len() != width) {
+        bail!(
+            "Row {} is {} wide, but the first row is {} wide",
+            i + 1,
+            rows[i].len(),
+            width
+        );
+    }
     let mut x = 0;
     let mut n_trees = 0;
-    let width = rows[0].len();
     let iter = rows.iter().step_by(trajectory.1);
     for row in iter {
         let coordinate = row[x % width];
@@ -53,21 +72,42 @@ fn n_trees(rows: &[Row], trajectory: &(usize, usize)) -> usize {
         }
         x += trajectory.0;
     }
-    n_trees
+    Ok(n_trees)
 }
 
 #[aoc(day3, part1)]
-fn part1(input: &[Row]) -> usize {
-    n_trees(&input, &(3, 1))
+fn part1(input: &[Row]) -> Result<usize> {
+    n_trees(input, &(3, 1))
 }
 
 #[aoc(day3, part2)]
-fn part2(input: &[Row]) -> usize {
+fn part2(input: &[Row]) -> Result<usize> {
     let trajectories = &[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
-    trajectories
-        .iter()
-        .map(|traj| n_trees(&input, traj))
-        .product()
+    let mut product = 1;
+    for traj in trajectories {
+        product *= n_trees(input, traj)?;
+    }
+    Ok(product)
+}
+
+pub struct Day3;
+
+impl Solution for Day3 {
+    const DAY: u8 = 3;
+    type Input = Vec<Row>;
+    type Output = usize;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -78,8 +118,9 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "..##.......
+            part1(
+                &parse_input(
+                    "..##.......
 #...#...#..
 .#....#..#.
 ..#.#...#.#
@@ -90,7 +131,10 @@ mod tests {
 #.##...#...
 #...##....#
 .#..#...#.#"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             7
         );
     }
@@ -98,8 +142,9 @@ mod tests {
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "..##.......
+            part2(
+                &parse_input(
+                    "..##.......
 #...#...#..
 .#....#..#.
 ..#.#...#.#
@@ -110,8 +155,29 @@ mod tests {
 #.##...#...
 #...##....#
 .#..#...#.#"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             336
         );
     }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse_input("..#\n.#.\n#?.").unwrap_err();
+        assert_eq!((err.line, err.column, err.text.as_str()), (3, 2, "#?."));
+    }
+
+    #[test]
+    fn test_invalid_map() {
+        assert!(part1(&[]).is_err());
+        assert!(part1(&parse_input("\n").unwrap()).is_err());
+        assert_eq!(
+            part2(&parse_input("..#\n.#").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "Row 2 is 2 wide, but the first row is 3 wide"
+        );
+    }
 }
@@ -1,118 +1,533 @@
-use anyhow::Result;
-use serde::Deserialize;
+use std::{collections::BTreeMap, convert::TryFrom, fmt, io::Write, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-const VALID_EYE_COLOURS: &[&str] = &["amb", "blu", "brn", "gry", "grn", "hzl", "oth"];
+use crate::{
+    error::blocks,
+    measurement::{Height, LengthUnit},
+    schema::{Document, Schema, ValidationReport},
+    solution::Solution,
+};
+
+/// The puzzle's passport rules.
+pub static PASSPORT_SCHEMA: Lazy<Schema> = Lazy::new(|| {
+    "
+fields:
+  - name: byr
+    rule: { type: year, min: 1920, max: 2002 }
+  - name: iyr
+    rule: { type: year, min: 2010, max: 2020 }
+  - name: eyr
+    rule: { type: year, min: 2020, max: 2030 }
+  - name: hgt
+    rule:
+      type: measurement
+      units:
+        cm: { min: 150, max: 193 }
+        in: { min: 59, max: 76 }
+  - name: hcl
+    rule: { type: hex_colour }
+  - name: ecl
+    rule: { type: enum, values: [amb, blu, brn, gry, grn, hzl, oth] }
+  - name: pid
+    rule: { type: digits, length: 9 }
+  - name: cid
+    required: false
+"
+    .parse()
+    .unwrap()
+});
+
+#[derive(Debug, PartialEq)]
+pub enum PassportError {
+    /// A token which isn't of the form `key:value`.
+    MalformedField(String),
+    /// A field which appeared more than once.
+    DuplicateField(String),
+    /// Required fields which were absent, in the order the schema lists
+    /// them, and the record which was missing them.
+    MissingFields { fields: Vec<String>, record: Record },
+}
+
+impl fmt::Display for PassportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedField(token) => write!(f, "Malformed field: {}", token),
+            Self::DuplicateField(key) => write!(f, "Duplicate field: {}", key),
+            Self::MissingFields { fields, .. } => {
+                write!(f, "Missing fields: {}", fields.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassportError {}
+
+/// The `key:value` fields of a single record, in the order they appeared.
+///
+/// Fields may be separated by any whitespace, including newlines. Values run
+/// until the next whitespace, so may themselves contain `:`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Record(Vec<(String, String)>);
+
+impl Record {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl FromStr for Record {
+    type Err = PassportError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for token in s.split_whitespace() {
+            let mut iter = token.splitn(2, ':');
+            let key = iter.next().filter(|k| !k.is_empty());
+            let (key, value) = match (key, iter.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Err(PassportError::MalformedField(token.to_string())),
+            };
+            if fields.iter().any(|(k, _)| k == key) {
+                return Err(PassportError::DuplicateField(key.to_string()));
+            }
+            fields.push((key.to_string(), value.to_string()));
+        }
+        Ok(Self(fields))
+    }
+}
 
-#[derive(Debug, Deserialize)]
-struct Passport {
-    byr: String,
-    iyr: String,
-    eyr: String,
-    hgt: String,
-    hcl: String,
-    ecl: String,
-    pid: String,
-    cid: Option<String>,
+/// A record with every field its schema requires.
+#[derive(Debug)]
+pub struct Passport {
+    fields: BTreeMap<String, String>,
 }
 
 impl Passport {
-    fn validate_byr(&self) -> Result<bool> {
-        Ok(self.byr.parse::<u16>().map(|x| x >= 1920 && x <= 2002)?)
+    /// Check that `record` has every field `schema` requires.
+    pub fn new(record: Record, schema: &Schema) -> Result<Self, PassportError> {
+        let missing: Vec<String> = schema
+            .missing_fields(&record)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if !missing.is_empty() {
+            return Err(PassportError::MissingFields {
+                fields: missing,
+                record,
+            });
+        }
+        Ok(Self {
+            fields: record.0.into_iter().collect(),
+        })
+    }
+
+    /// Look up a field by its key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    /// Every field, in order of key.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl TryFrom<Record> for Passport {
+    type Error = PassportError;
+    fn try_from(record: Record) -> Result<Self, Self::Error> {
+        Self::new(record, &PASSPORT_SCHEMA)
+    }
+}
+
+impl FromStr for Passport {
+    type Err = PassportError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.parse::<Record>()?)
+    }
+}
+
+impl Document for Record {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.get(name)
     }
+}
 
-    fn validate_iyr(&self) -> Result<bool> {
-        Ok(self.iyr.parse::<u16>().map(|x| x >= 2010 && x <= 2020)?)
+impl Document for Passport {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.get(name)
     }
+}
 
-    fn validate_eyr(&self) -> Result<bool> {
-        Ok(self.eyr.parse::<u16>().map(|x| x >= 2020 && x <= 2030)?)
+impl Passport {
+    /// Check every field of the passport against the puzzle's rules,
+    /// reporting each rule it breaks.
+    pub fn validate(&self) -> ValidationReport {
+        self.validate_with(&PASSPORT_SCHEMA)
     }
 
-    fn validate_hgt(&self) -> bool {
-        match self.hgt.get((self.hgt.len() - 2)..) {
-            Some("in") => self
-                .hgt
-                .get(..2)
-                .unwrap()
-                .parse::<u16>()
-                .map_or(false, |height| height >= 59 && height <= 76),
-            Some("cm") => self
-                .hgt
-                .get(..3)
-                .unwrap()
-                .parse::<u16>()
-                .map_or(false, |height| height >= 150 && height <= 193),
-            _ => false,
+    /// Check every field of the passport against `schema`.
+    pub fn validate_with(&self, schema: &Schema) -> ValidationReport {
+        schema.validate(self)
+    }
+}
+
+/// Tallies of validation results across a batch of passports.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub valid: usize,
+    /// Passports with all required fields, which broke at least one rule.
+    pub invalid: usize,
+    /// Passports with missing fields.
+    pub incomplete: usize,
+    /// Passports which couldn't be parsed at all.
+    pub unparsable: usize,
+    /// The number of passports failing validation (or missing) each field.
+    pub failures: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    pub fn new(passports: &[Result<Passport, PassportError>]) -> Self {
+        Self::with_schema(passports, &PASSPORT_SCHEMA)
+    }
+
+    /// Tally `passports`, validating them against `schema`.
+    pub fn with_schema(passports: &[Result<Passport, PassportError>], schema: &Schema) -> Self {
+        let mut summary = Self::default();
+        for passport in passports {
+            match passport {
+                Ok(passport) => {
+                    let report = passport.validate_with(schema);
+                    if report.is_valid() {
+                        summary.valid += 1;
+                    } else {
+                        summary.invalid += 1;
+                    }
+                    for failure in report.failures {
+                        *summary.failures.entry(failure.field).or_default() += 1;
+                    }
+                }
+                Err(PassportError::MissingFields { fields, .. }) => {
+                    summary.incomplete += 1;
+                    for field in fields {
+                        *summary.failures.entry(field.clone()).or_default() += 1;
+                    }
+                }
+                Err(_) => summary.unparsable += 1,
+            }
         }
+        summary
     }
+}
 
-    fn validate_hcl(&self) -> bool {
-        self.hcl.starts_with('#')
-            && self
-                .hcl
-                .chars()
-                .skip(1)
-                .all(|c| (c.is_lowercase() || c.is_numeric()) && c.is_ascii_hexdigit())
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} valid, {} invalid, {} incomplete, {} unparsable",
+            self.valid, self.invalid, self.incomplete, self.unparsable
+        )?;
+        for (field, count) in &self.failures {
+            write!(f, "\n\t{}: {}", field, count)?;
+        }
+        Ok(())
     }
+}
+
+/// The status of an exported passport.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    Valid,
+    Invalid,
+    /// Missing required fields.
+    Incomplete,
+    Unparsable,
+}
 
-    fn validate_ecl(&self) -> bool {
-        VALID_EYE_COLOURS.contains(&self.ecl.as_str())
+impl ExportStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Valid => "valid",
+            Self::Invalid => "invalid",
+            Self::Incomplete => "incomplete",
+            Self::Unparsable => "unparsable",
+        }
     }
+}
+
+/// A single passport as exported by `export`, with each field converted to
+/// its natural type.
+///
+/// Fields which are missing, or can't be converted, are `None`; the reason
+/// is listed in `errors`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ExportRecord {
+    /// The 0-based position of the passport in the batch.
+    pub index: usize,
+    pub status: ExportStatus,
+    pub byr: Option<u16>,
+    pub iyr: Option<u16>,
+    pub eyr: Option<u16>,
+    /// The height in centimetres.
+    pub hgt_cm: Option<f64>,
+    /// The hair colour as `[red, green, blue]`.
+    pub hcl: Option<[u8; 3]>,
+    pub ecl: Option<String>,
+    pub pid: Option<String>,
+    pub cid: Option<String>,
+    /// Any fields without a column of their own.
+    pub other: BTreeMap<String, String>,
+    pub errors: Vec<String>,
+}
+
+/// The fields `ExportRecord` has a column for.
+const EXPORTED_FIELDS: &[&str] = &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
 
-    fn validate_pid(&self) -> bool {
-        self.pid.parse::<u32>().is_ok() && self.pid.len() == 9
+/// Parse a `#rrggbb` colour.
+fn parse_rgb(s: &str) -> Option<[u8; 3]> {
+    let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl ExportRecord {
+    /// Convert a passport, validating it against `schema`.
+    pub fn new(index: usize, passport: &Result<Passport, PassportError>, schema: &Schema) -> Self {
+        match passport {
+            Ok(passport) => {
+                let report = passport.validate_with(schema);
+                let status = if report.is_valid() {
+                    ExportStatus::Valid
+                } else {
+                    ExportStatus::Invalid
+                };
+                let errors = report.failures.iter().map(ToString::to_string).collect();
+                Self::from_fields(index, status, passport, passport.fields(), errors)
+            }
+            Err(e @ PassportError::MissingFields { record, .. }) => Self::from_fields(
+                index,
+                ExportStatus::Incomplete,
+                record,
+                record.fields(),
+                vec![e.to_string()],
+            ),
+            Err(e) => Self {
+                index,
+                status: ExportStatus::Unparsable,
+                byr: None,
+                iyr: None,
+                eyr: None,
+                hgt_cm: None,
+                hcl: None,
+                ecl: None,
+                pid: None,
+                cid: None,
+                other: BTreeMap::new(),
+                errors: vec![e.to_string()],
+            },
+        }
     }
 
-    fn validate(&self) -> Result<bool> {
-        Ok(self.validate_byr()?
-            && self.validate_iyr()?
-            && self.validate_eyr()?
-            && self.validate_hgt()
-            && self.validate_hcl()
-            && self.validate_ecl()
-            && self.validate_pid())
+    /// Fill the exported columns from `document`, keeping any of `fields`
+    /// without a column of their own in `other`.
+    fn from_fields<'a>(
+        index: usize,
+        status: ExportStatus,
+        document: &impl Document,
+        fields: impl Iterator<Item = (&'a str, &'a str)>,
+        errors: Vec<String>,
+    ) -> Self {
+        let year = |key| document.field(key).and_then(|year| year.parse().ok());
+        let text = |key| document.field(key).map(String::from);
+        Self {
+            index,
+            status,
+            byr: year("byr"),
+            iyr: year("iyr"),
+            eyr: year("eyr"),
+            hgt_cm: document
+                .field("hgt")
+                .and_then(|hgt| hgt.parse::<Height>().ok())
+                .map(|hgt| hgt.to(LengthUnit::Centimetre)),
+            hcl: document.field("hcl").and_then(parse_rgb),
+            ecl: text("ecl"),
+            pid: text("pid"),
+            cid: text("cid"),
+            other: fields
+                .filter(|(key, _)| !EXPORTED_FIELDS.contains(key))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            errors,
+        }
     }
 }
 
-#[aoc_generator(day4)]
-fn parse_input(input: &str) -> Vec<Result<Passport>> {
-    input
-        .split("\n\n")
-        .map(|el| {
-            // The passports look sorta kinda like YAML if you hack it around a bit?
-            // Saves implementing a bunch of parsing logic.
-            Ok(serde_yaml::from_str(&format!(
-                "{}\"",
-                &el.replace('\n', "\"\n")
-                    .replace(' ', "\"\n")
-                    .replace(':', ": \"")
-            ))?)
-        })
+/// The formats supported by `export`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// CSV with a header row. The hair colour is split into `hcl_r`, `hcl_g`
+    /// and `hcl_b` columns, other fields are written as space-separated
+    /// `key:value` pairs, and errors are joined with `; `.
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("Unknown export format {}, expected jsonl or csv", s),
+        }
+    }
+}
+
+const CSV_HEADER: &[&str] = &[
+    "index", "status", "byr", "iyr", "eyr", "hgt_cm", "hcl_r", "hcl_g", "hcl_b", "ecl", "pid",
+    "cid", "other", "errors",
+];
+
+/// Quote a CSV field if it contains anything which would otherwise break the
+/// row.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or_else(String::new, ToString::to_string)
+}
+
+impl ExportRecord {
+    fn csv_row(&self) -> Vec<String> {
+        let hcl = |i: usize| csv_optional(&self.hcl.map(|rgb| rgb[i]));
+        vec![
+            self.index.to_string(),
+            self.status.as_str().to_string(),
+            csv_optional(&self.byr),
+            csv_optional(&self.iyr),
+            csv_optional(&self.eyr),
+            csv_optional(&self.hgt_cm),
+            hcl(0),
+            hcl(1),
+            hcl(2),
+            csv_optional(&self.ecl),
+            csv_optional(&self.pid),
+            csv_optional(&self.cid),
+            self.other
+                .iter()
+                .map(|(key, value)| format!("{}:{}", key, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+            self.errors.join("; "),
+        ]
+    }
+}
+
+/// Write every passport, valid or not, to `writer` in the given format,
+/// validating each against `schema`.
+pub fn export(
+    passports: &[Result<Passport, PassportError>],
+    schema: &Schema,
+    format: ExportFormat,
+    mut writer: impl Write,
+) -> Result<()> {
+    let records = passports
+        .iter()
+        .enumerate()
+        .map(|(i, passport)| ExportRecord::new(i, passport, schema));
+    match format {
+        ExportFormat::JsonLines => {
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        ExportFormat::Csv => {
+            writeln!(writer, "{}", CSV_HEADER.join(","))?;
+            for record in records {
+                let row: Vec<_> = record.csv_row().iter().map(|f| csv_field(f)).collect();
+                writeln!(writer, "{}", row.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse every blank-line separated passport in `input`, checking each has
+/// the fields `schema` requires.
+pub fn parse_passports(input: &str, schema: &Schema) -> Vec<Result<Passport, PassportError>> {
+    blocks(input)
+        .into_iter()
+        .map(|(_, block)| Passport::new(block.parse()?, schema))
         .collect()
 }
 
+#[aoc_generator(day4)]
+fn parse_input(input: &str) -> Vec<Result<Passport, PassportError>> {
+    parse_passports(input, &PASSPORT_SCHEMA)
+}
+
 #[aoc(day4, part1)]
-fn part1(input: &[Result<Passport>]) -> u32 {
+fn part1(input: &[Result<Passport, PassportError>]) -> u32 {
     input.iter().map(|x| if x.is_ok() { 1 } else { 0 }).sum()
 }
 
 #[aoc(day4, part2)]
-fn part2(input: &[Result<Passport>]) -> u32 {
+fn part2(input: &[Result<Passport, PassportError>]) -> u32 {
     input
         .iter()
-        .filter_map(|x| {
-            x.as_ref()
-                .ok()
-                .map(|passport| passport.validate().map_or(0, |x| if x { 1 } else { 0 }))
-        })
+        .filter_map(|x| x.as_ref().ok())
+        .map(|passport| if passport.validate().is_valid() { 1 } else { 0 })
         .sum()
 }
 
+#[aoc(day4, part2, Summary)]
+fn part2_summary(input: &[Result<Passport, PassportError>]) -> Summary {
+    Summary::new(input)
+}
+
+pub struct Day4;
+
+impl Solution for Day4 {
+    const DAY: u8 = 4;
+    type Input = Vec<Result<Passport, PassportError>>;
+    type Output = u32;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input))
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part1(input))
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part2(input))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
+    use crate::schema::RuleFailure;
+
     use super::*;
 
     #[test]
@@ -172,4 +587,210 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719"
             4
         );
     }
+
+    #[test]
+    fn test_parse_record() {
+        let record: Record = "a:1 b:x:y\r\n\tc:#zz\r\n".parse().unwrap();
+        assert_eq!(
+            record.fields().collect::<Vec<_>>(),
+            vec![("a", "1"), ("b", "x:y"), ("c", "#zz")]
+        );
+        assert_eq!(
+            "a:1 b:2 a:3".parse::<Record>(),
+            Err(PassportError::DuplicateField("a".to_string()))
+        );
+        assert_eq!(
+            "a:1 b c:3".parse::<Record>(),
+            Err(PassportError::MalformedField("b".to_string()))
+        );
+        assert_eq!(
+            "a:1 :2".parse::<Record>(),
+            Err(PassportError::MalformedField(":2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_passport() {
+        let passport: Passport = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm nat:gb"
+            .parse()
+            .unwrap();
+        assert_eq!(passport.get("cid"), Some("147"));
+        assert_eq!(passport.get("nat"), Some("gb"));
+        assert_eq!(passport.get("xyz"), None);
+        let err = "hcl:#cfa07d eyr:2025 pid:166559648 iyr:2011 ecl:brn hgt:59in"
+            .parse::<Passport>()
+            .unwrap_err();
+        assert_eq!(missing(&err), vec!["byr"]);
+        match err {
+            PassportError::MissingFields { record, .. } => {
+                assert_eq!(record.get("pid"), Some("166559648"))
+            }
+            _ => unreachable!(),
+        }
+        let errors = parse_input("iyr:2013 ecl:amb\r\n\r\nbyr:1929 \r\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|passport| missing(passport.as_ref().unwrap_err()))
+                .collect::<Vec<_>>(),
+            vec![
+                vec!["byr", "eyr", "hgt", "hcl", "pid"],
+                vec!["iyr", "eyr", "hgt", "hcl", "ecl", "pid"],
+            ]
+        );
+    }
+
+    /// The fields a passport is missing.
+    fn missing(err: &PassportError) -> Vec<&str> {
+        match err {
+            PassportError::MissingFields { fields, .. } => {
+                fields.iter().map(String::as_str).collect()
+            }
+            _ => panic!("Expected missing fields, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_custom_schema() {
+        let schema: Schema = "
+fields:
+  - name: byr
+    rule: { type: year, min: 1900, max: 2000 }
+  - name: cid
+"
+        .parse()
+        .unwrap();
+        let passports = parse_passports(
+            "byr:1950 cid:1\n\nbyr:2001 cid:1\n\ncid:2 pid:x\n\nbyr:1950",
+            &schema,
+        );
+        assert_eq!(part1(&passports), 2);
+        assert_eq!(missing(passports[2].as_ref().unwrap_err()), vec!["byr"]);
+        assert_eq!(
+            Summary::with_schema(&passports, &schema).to_string(),
+            "1 valid, 1 invalid, 2 incomplete, 0 unparsable\n\tbyr: 2\n\tcid: 1"
+        );
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let passport: Passport =
+            "byr:1919 iyr:2010 eyr:2040 hgt:200cm hcl:123abc ecl:xyz pid:0123456789"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            passport.validate().to_string(),
+            "byr: 1919 is below min 1920
+eyr: 2040 exceeds max 2030
+hgt: 200cm exceeds max 193cm
+hcl: expected # followed by lowercase hex digits, got 123abc
+ecl: xyz is not one of amb, blu, brn, gry, grn, hzl, oth
+pid: expected 9 digits, got 10"
+        );
+        let passport: Passport =
+            "byr:2002 iyr:2020 eyr:2020 hgt:6in hcl:#abcdef ecl:amb pid:000000001"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            passport.validate().failures,
+            vec![RuleFailure {
+                field: "hgt".to_string(),
+                message: "6in is below min 59in".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new(&parse_input(
+            "byr:1919 iyr:2010 eyr:2030 hgt:150cm hcl:#123abc ecl:amb pid:012345678
+
+byr:1920 iyr:2010 eyr:2030 hgt:hmm hcl:#123abc ecl:amb pid:012345678
+
+byr:1920 iyr:2010 eyr:2030 hgt:59in hcl:#123abc ecl:amb pid:012345678
+
+iyr:2010 eyr:2030 hgt:59in hcl:#123abc ecl:amb
+
+byr:1920 byr:1920",
+        ));
+        assert_eq!(
+            summary,
+            Summary {
+                valid: 1,
+                invalid: 2,
+                incomplete: 1,
+                unparsable: 1,
+                failures: vec![("byr", 2), ("hgt", 1), ("pid", 1)]
+                    .into_iter()
+                    .map(|(field, count)| (field.to_string(), count))
+                    .collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_hgt() {
+        let report = |hgt| {
+            format!(
+                "byr:1980 iyr:2015 eyr:2025 hgt:{} hcl:#abcdef ecl:amb pid:000000001",
+                hgt
+            )
+            .parse::<Passport>()
+            .unwrap()
+            .validate()
+            .to_string()
+        };
+        assert_eq!(report("193cm"), "valid");
+        assert_eq!(report("59in"), "valid");
+        assert_eq!(report("1500cm"), "hgt: 1500cm exceeds max 193cm");
+        assert_eq!(report("6in"), "hgt: 6in is below min 59in");
+        for hgt in &["", "c", "cm", "1.8m", "5'11\""] {
+            assert_eq!(
+                report(hgt),
+                format!("hgt: expected a measurement in cm or in, got {}", hgt)
+            );
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let passports = parse_input(
+            "byr:1980 iyr:2015 eyr:2025 hgt:74in hcl:#ff8000 ecl:amb pid:000000001 cid:1 nat:gb zz:9
+
+byr:1919 iyr:2015 eyr:2025 hgt:1500cm hcl:z,z ecl:amb pid:000000001
+
+byr:1980 nat:fr
+
+byr:1980 byr:1981",
+        );
+        let mut jsonl = Vec::new();
+        export(
+            &passports,
+            &PASSPORT_SCHEMA,
+            ExportFormat::JsonLines,
+            &mut jsonl,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            r#"{"index":0,"status":"valid","byr":1980,"iyr":2015,"eyr":2025,"hgt_cm":187.96,"hcl":[255,128,0],"ecl":"amb","pid":"000000001","cid":"1","other":{"nat":"gb","zz":"9"},"errors":[]}
+{"index":1,"status":"invalid","byr":1919,"iyr":2015,"eyr":2025,"hgt_cm":1500.0,"hcl":null,"ecl":"amb","pid":"000000001","cid":null,"other":{},"errors":["byr: 1919 is below min 1920","hgt: 1500cm exceeds max 193cm","hcl: expected # followed by lowercase hex digits, got z,z"]}
+{"index":2,"status":"incomplete","byr":1980,"iyr":null,"eyr":null,"hgt_cm":null,"hcl":null,"ecl":null,"pid":null,"cid":null,"other":{"nat":"fr"},"errors":["Missing fields: iyr, eyr, hgt, hcl, ecl, pid"]}
+{"index":3,"status":"unparsable","byr":null,"iyr":null,"eyr":null,"hgt_cm":null,"hcl":null,"ecl":null,"pid":null,"cid":null,"other":{},"errors":["Duplicate field: byr"]}
+"#
+        );
+
+        let mut csv = Vec::new();
+        export(&passports, &PASSPORT_SCHEMA, ExportFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            r#"index,status,byr,iyr,eyr,hgt_cm,hcl_r,hcl_g,hcl_b,ecl,pid,cid,other,errors
+0,valid,1980,2015,2025,187.96,255,128,0,amb,000000001,1,nat:gb zz:9,
+1,invalid,1919,2015,2025,1500,,,,amb,000000001,,,"byr: 1919 is below min 1920; hgt: 1500cm exceeds max 193cm; hcl: expected # followed by lowercase hex digits, got z,z"
+2,incomplete,1980,,,,,,,,,,nat:fr,"Missing fields: iyr, eyr, hgt, hcl, ecl, pid"
+3,unparsable,,,,,,,,,,,,Duplicate field: byr
+"#
+        );
+    }
 }
@@ -1,19 +1,58 @@
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Range,
+    str::FromStr,
+};
 
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ColumnError, ParseError};
+use crate::solution::Solution;
+
+/// Decode a binary space partitioning code, where `alphabet[0]` means "take
+/// the lower half" and `alphabet[1]` means "take the upper half".
+fn decode(code: &str, alphabet: [char; 2]) -> Result<u16> {
+    code.char_indices().try_fold(0u16, |acc, (i, c)| {
+        let bit = alphabet.iter().position(|&a| a == c).ok_or_else(|| {
+            ColumnError::new(
+                i,
+                anyhow!(
+                    "Unexpected {:?}, expected {} or {}",
+                    c,
+                    alphabet[0],
+                    alphabet[1]
+                ),
+            )
+        })?;
+        acc.checked_mul(2)
+            .map(|acc| acc + bit as u16)
+            .ok_or_else(|| anyhow!("Code {} is too long", code))
+    })
+}
+
+/// Encode `value` as a binary space partitioning code of length `bits`.
+fn encode(value: u16, bits: u32, alphabet: [char; 2]) -> String {
+    (0..bits)
+        .rev()
+        .map(|bit| alphabet[usize::from(value >> bit & 1)])
+        .collect()
+}
+
+/// The number of bits needed to represent `count` distinct values.
+fn bits_for(count: u16) -> u32 {
+    16 - count.saturating_sub(1).leading_zeros()
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 struct Row(u16);
 
 impl FromStr for Row {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(Self(u16::from_str_radix(
-            &s.replace('F', "0").replace('B', "1"),
-            2,
-        )?))
+        Ok(Self(decode(s, PlaneLayout::default().row_alphabet)?))
     }
 }
 
@@ -23,63 +62,457 @@ struct Column(u16);
 impl FromStr for Column {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(Self(u16::from_str_radix(
-            &s.replace('L', "0").replace('R', "1"),
-            2,
-        )?))
+        Ok(Self(decode(s, PlaneLayout::default().column_alphabet)?))
     }
 }
 
+/// The seating layout of an aircraft, and how its seats are encoded on
+/// boarding passes.
+///
+/// Codes are a row part followed by a column part, each using just enough
+/// characters to distinguish every row (or column), so counts which aren't a
+/// power of two leave some codes unused.
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct Seat {
+pub struct PlaneLayout {
+    rows: u16,
+    columns: u16,
+    /// The characters meaning "front half" and "back half" of the rows.
+    row_alphabet: [char; 2],
+    /// The characters meaning "left half" and "right half" of the columns.
+    column_alphabet: [char; 2],
+    /// Seat IDs are `row * id_multiplier + column`.
+    id_multiplier: u32,
+}
+
+/// The layout from the puzzle: 128 rows of 8 seats, encoded as e.g.
+/// `FBFBBFFRLR`.
+impl Default for PlaneLayout {
+    fn default() -> Self {
+        Self {
+            rows: 128,
+            columns: 8,
+            row_alphabet: ['F', 'B'],
+            column_alphabet: ['L', 'R'],
+            id_multiplier: 8,
+        }
+    }
+}
+
+impl PlaneLayout {
+    /// A layout with `rows` rows of `columns` seats, and seat IDs
+    /// `row * id_multiplier + column`, encoded with the puzzle's alphabets.
+    ///
+    /// Fails unless every seat gets a distinct ID which fits in a `u32`.
+    pub fn new(rows: u16, columns: u16, id_multiplier: u32) -> Result<Self> {
+        if id_multiplier < u32::from(columns.max(1)) {
+            bail!(
+                "An ID multiplier of {} gives duplicate IDs for {} columns",
+                id_multiplier,
+                columns
+            );
+        }
+        if u32::from(rows).checked_mul(id_multiplier).is_none() {
+            bail!(
+                "Seat IDs for {} rows with an ID multiplier of {} don't fit in 32 bits",
+                rows,
+                id_multiplier
+            );
+        }
+        Ok(Self {
+            rows,
+            columns,
+            id_multiplier,
+            ..Self::default()
+        })
+    }
+
+    /// Use different characters for the halves of the rows and columns.
+    pub fn with_alphabets(
+        self,
+        row_alphabet: [char; 2],
+        column_alphabet: [char; 2],
+    ) -> Result<Self> {
+        for alphabet in &[row_alphabet, column_alphabet] {
+            if alphabet[0] == alphabet[1] {
+                bail!("Both halves are encoded as {:?}", alphabet[0]);
+            }
+        }
+        Ok(Self {
+            row_alphabet,
+            column_alphabet,
+            ..self
+        })
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    fn row_bits(&self) -> u32 {
+        bits_for(self.rows)
+    }
+
+    fn column_bits(&self) -> u32 {
+        bits_for(self.columns)
+    }
+
+    /// The length of every boarding pass code for this layout.
+    pub fn code_len(&self) -> usize {
+        (self.row_bits() + self.column_bits()) as usize
+    }
+
+    /// Decode a boarding pass code.
+    pub fn parse_seat(&self, code: &str) -> Result<Seat> {
+        let len = code.chars().count();
+        if len != self.code_len() {
+            bail!(
+                "Expected a {} character boarding pass, got {} characters",
+                self.code_len(),
+                len
+            );
+        }
+        let split = code
+            .char_indices()
+            .nth(self.row_bits() as usize)
+            .map_or(code.len(), |(i, _)| i);
+        let row = decode(&code[..split], self.row_alphabet)?;
+        let column = decode(&code[split..], self.column_alphabet).map_err(|e| match e
+            .downcast::<ColumnError>()
+        {
+            Ok(e) => ColumnError::new(split + e.offset, e).into(),
+            Err(e) => e,
+        })?;
+        if row >= self.rows {
+            bail!("Row {} is out of range for {} rows", row, self.rows);
+        }
+        if column >= self.columns {
+            bail!(
+                "Column {} is out of range for {} columns",
+                column,
+                self.columns
+            );
+        }
+        Ok(Seat {
+            row: Row(row),
+            column: Column(column),
+        })
+    }
+
+    /// Encode a seat as a boarding pass code.
+    pub fn encode(&self, seat: Seat) -> String {
+        encode(seat.row.0, self.row_bits(), self.row_alphabet)
+            + &encode(seat.column.0, self.column_bits(), self.column_alphabet)
+    }
+
+    pub fn seat_id(&self, seat: Seat) -> u32 {
+        u32::from(seat.row.0) * self.id_multiplier + u32::from(seat.column.0)
+    }
+
+    /// Find the seat with the given ID.
+    pub fn seat_from_id(&self, id: u32) -> Result<Seat> {
+        let row = id / self.id_multiplier;
+        let column = id % self.id_multiplier;
+        if row >= self.rows.into() || column >= self.columns.into() {
+            bail!("Seat ID {} is out of range", id);
+        }
+        Ok(Seat {
+            row: Row(row as u16),
+            column: Column(column as u16),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Seat {
     row: Row,
     column: Column,
 }
 
 impl Seat {
-    fn id(self) -> u32 {
-        (self.row.0 * 8 + self.column.0).into()
+    pub fn row(self) -> u16 {
+        self.row.0
+    }
+
+    pub fn column(self) -> u16 {
+        self.column.0
+    }
+
+    /// The seat's ID, using the puzzle's layout.
+    pub fn id(self) -> u32 {
+        PlaneLayout::default().seat_id(self)
+    }
+
+    /// Find the seat with the given ID, using the puzzle's layout.
+    pub fn from_id(id: u32) -> Result<Self> {
+        PlaneLayout::default().seat_from_id(id)
+    }
+}
+
+/// Formats the seat as its boarding pass code using the puzzle's layout,
+/// e.g. `FBFBBFFRLR`.
+impl fmt::Display for Seat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&PlaneLayout::default().encode(*self))
     }
 }
 
 impl FromStr for Seat {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(Self {
-            row: s
-                .get(..7)
-                .context("Seat spec too short!")
-                .and_then(|x| Ok(x.parse()?))?,
-            column: s
-                .get(7..)
-                .context("Seat spec too short!")
-                .and_then(|x| Ok(x.parse()?))?,
-        })
+        PlaneLayout::default().parse_seat(s)
+    }
+}
+
+/// Which seats of a plane have boarding passes.
+#[derive(Debug, Default, PartialEq)]
+pub struct Occupancy {
+    /// The number of boarding passes for each seat ID.
+    pub passes: BTreeMap<u32, usize>,
+    /// Seat IDs between the lowest and highest occupied seats without a
+    /// boarding pass.
+    pub missing: BTreeSet<u32>,
+    /// Seat IDs with more than one boarding pass.
+    pub duplicates: Vec<u32>,
+    /// Rows in front of the first occupied seat, which aren't present on
+    /// this flight.
+    pub absent_front_rows: Range<u16>,
+    /// Rows behind the last occupied seat, which aren't present on this
+    /// flight.
+    pub absent_back_rows: Range<u16>,
+}
+
+impl Occupancy {
+    pub fn new(seats: &[Seat], layout: &PlaneLayout) -> Self {
+        let mut passes = BTreeMap::new();
+        for seat in seats {
+            *passes.entry(layout.seat_id(*seat)).or_insert(0) += 1;
+        }
+        let (first, last) = match (passes.keys().next(), passes.keys().last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => {
+                return Self {
+                    absent_front_rows: 0..layout.rows,
+                    ..Self::default()
+                }
+            }
+        };
+        let row = |id| layout.seat_from_id(id).map_or(0, Seat::row);
+        Self {
+            missing: (first..=last)
+                // Skip IDs which don't correspond to a seat in this layout.
+                .filter(|id| !passes.contains_key(id) && layout.seat_from_id(*id).is_ok())
+                .collect(),
+            duplicates: passes
+                .iter()
+                .filter(|(_, &count)| count > 1)
+                .map(|(&id, _)| id)
+                .collect(),
+            absent_front_rows: 0..row(first),
+            absent_back_rows: row(last) + 1..layout.rows,
+            passes,
+        }
+    }
+}
+
+/// The state of a single seat, as shown on a seat map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeatState {
+    Occupied,
+    /// More than one boarding pass was issued for the seat.
+    Duplicate,
+    /// A gap between occupied seats.
+    Missing,
+    /// Empty, but outside the occupied part of the plane.
+    Empty,
+}
+
+impl SeatState {
+    fn symbol(self) -> char {
+        match self {
+            Self::Occupied => '#',
+            Self::Duplicate => '!',
+            Self::Missing => 'o',
+            Self::Empty => '.',
+        }
+    }
+
+    fn colour(self) -> &'static str {
+        match self {
+            Self::Occupied => "#4a90d9",
+            Self::Duplicate => "#d0021b",
+            Self::Missing => "#f5a623",
+            Self::Empty => "#e6e6e6",
+        }
+    }
+}
+
+/// The label for a column on a seat map: `A`, `B`, ... `Z`, `AA`, `AB`...
+fn column_label(column: u16) -> String {
+    let mut label = Vec::new();
+    let mut n = u32::from(column) + 1;
+    while n > 0 {
+        n -= 1;
+        label.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    label.iter().rev().map(|&b| char::from(b)).collect()
+}
+
+/// Size of each seat on an SVG seat map, in pixels.
+const SVG_SEAT: u32 = 20;
+/// Width of an aisle on an SVG seat map, in pixels.
+const SVG_AISLE: u32 = 10;
+/// Width of the row number margin on an SVG seat map, in pixels.
+const SVG_MARGIN: u32 = 40;
+
+impl Occupancy {
+    pub fn state(&self, id: u32) -> SeatState {
+        match self.passes.get(&id) {
+            Some(1) => SeatState::Occupied,
+            Some(_) => SeatState::Duplicate,
+            None if self.missing.contains(&id) => SeatState::Missing,
+            None => SeatState::Empty,
+        }
+    }
+
+    /// Render the occupancy as a text seat map, with one line per row.
+    ///
+    /// Seats are shown as `#` (occupied), `!` (duplicate boarding passes),
+    /// `o` (missing) or `.` (empty), with an aisle after each column index in
+    /// `aisles`. Past 26 columns, seats are padded to the width of the
+    /// longest column label and separated by spaces.
+    pub fn render_text(&self, layout: &PlaneLayout, aisles: &[u16]) -> String {
+        let width = layout.rows.saturating_sub(1).to_string().len();
+        let seat_width = column_label(layout.columns.saturating_sub(1)).len();
+        let cell = |text: &dyn fmt::Display| match seat_width {
+            1 => text.to_string(),
+            _ => format!(" {:>width$}", text.to_string(), width = seat_width),
+        };
+        let mut out = format!("{:width$} ", "", width = width);
+        for column in 0..layout.columns {
+            out.push_str(&cell(&column_label(column)));
+            if aisles.contains(&column) {
+                out.push(' ');
+            }
+        }
+        for row in 0..layout.rows {
+            out.push_str(&format!("\n{:width$} ", row, width = width));
+            for column in 0..layout.columns {
+                let seat = Seat {
+                    row: Row(row),
+                    column: Column(column),
+                };
+                out.push_str(&cell(&self.state(layout.seat_id(seat)).symbol()));
+                if aisles.contains(&column) {
+                    out.push(' ');
+                }
+            }
+        }
+        out
+    }
+
+    /// Render the occupancy as an SVG seat map, coloured as in
+    /// `render_text`.
+    pub fn render_svg(&self, layout: &PlaneLayout, aisles: &[u16]) -> String {
+        let x = |column: u16| {
+            let aisles_before = aisles.iter().filter(|&&a| a < column).count() as u32;
+            SVG_MARGIN + u32::from(column) * SVG_SEAT + aisles_before * SVG_AISLE
+        };
+        let y = |row: u16| SVG_SEAT + u32::from(row) * SVG_SEAT;
+        let width = x(layout.columns);
+        let height = y(layout.rows);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"monospace\" font-size=\"12\">\n",
+            width, height
+        );
+        for column in 0..layout.columns {
+            out.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                x(column) + SVG_SEAT / 2,
+                SVG_SEAT - 5,
+                column_label(column)
+            ));
+        }
+        for row in 0..layout.rows {
+            out.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+                SVG_MARGIN - 5,
+                y(row) + SVG_SEAT - 5,
+                row
+            ));
+            for column in 0..layout.columns {
+                let seat = Seat {
+                    row: Row(row),
+                    column: Column(column),
+                };
+                let id = layout.seat_id(seat);
+                out.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{} (ID {})</title></rect>\n",
+                    x(column) + 1,
+                    y(row) + 1,
+                    SVG_SEAT - 2,
+                    SVG_SEAT - 2,
+                    self.state(id).colour(),
+                    layout.encode(seat),
+                    id
+                ));
+            }
+        }
+        out.push_str("</svg>\n");
+        out
     }
 }
 
 #[aoc_generator(day5)]
-fn parse_input(input: &str) -> Vec<Seat> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<Seat>, ParseError> {
+    parse_lines(5, input)
 }
 
 #[aoc(day5, part1)]
-fn part1(input: &[Seat]) -> u32 {
-    input.iter().map(|s| s.id()).max().unwrap()
+fn part1(input: &[Seat]) -> Result<u32> {
+    input
+        .iter()
+        .map(|s| s.id())
+        .max()
+        .context("No boarding passes")
 }
 
 #[aoc(day5, part2)]
-fn part2(input: &[Seat]) -> u32 {
-    // We can determine the missing number by calculating the sum of (min..max)
-    // and comparing it to the observed sum.
-    let (min, max, observed_sum, len) = input
-        .iter()
-        .map(|x| x.id())
-        .fold((u32::MAX, u32::MIN, 0, 0), |(min, max, sum, len), x| {
-            (min.min(x), max.max(x), sum + x, len + 1)
-        });
-    // Use Gauss's method for calculating the sum of an arithmetic series.
-    ((len + 1) * (min + max) / 2) - observed_sum
+fn part2(input: &[Seat]) -> Result<u32> {
+    let occupancy = Occupancy::new(input, &PlaneLayout::default());
+    let missing = &occupancy.missing;
+    match missing.iter().next() {
+        Some(&id) if missing.len() == 1 => Ok(id),
+        _ => bail!(
+            "Expected exactly one missing seat, found {}: {:?}",
+            missing.len(),
+            missing
+        ),
+    }
+}
+
+pub struct Day5;
+
+impl Solution for Day5 {
+    const DAY: u8 = 5;
+    type Input = Vec<Seat>;
+    type Output = u32;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -136,4 +569,132 @@ mod tests {
         assert_eq!("FFFBBBFRRR".parse::<Seat>().unwrap().id(), 119);
         assert_eq!("BBFFBBFRLL".parse::<Seat>().unwrap().id(), 820);
     }
+
+    #[test]
+    fn seat_from_id() {
+        assert_eq!(Seat::from_id(357).unwrap().to_string(), "FBFBBFFRLR");
+        assert_eq!(Seat::from_id(820).unwrap().to_string(), "BBFFBBFRLL");
+        assert!(Seat::from_id(1024).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for id in 0..1024 {
+            let seat = Seat::from_id(id).unwrap();
+            let code = seat.to_string();
+            assert_eq!(code.parse::<Seat>().unwrap(), seat, "{}", code);
+            assert_eq!(seat.id(), id);
+        }
+    }
+
+    #[test]
+    fn custom_layout() {
+        // 100 rows of 6 seats: 7 row characters, 3 column characters.
+        let layout = PlaneLayout::new(100, 6, 6)
+            .unwrap()
+            .with_alphabets(['0', '1'], ['a', 'b'])
+            .unwrap();
+        let seat = layout.parse_seat("1100011bab").unwrap();
+        assert_eq!((seat.row(), seat.column()), (99, 5));
+        assert_eq!(layout.seat_id(seat), 599);
+        assert_eq!(
+            layout.encode(layout.seat_from_id(599).unwrap()),
+            "1100011bab"
+        );
+        assert!(layout.parse_seat("1100100aaa").is_err(), "row 100");
+        assert!(layout.parse_seat("0000000bba").is_err(), "column 6");
+        assert!(layout.seat_from_id(600).is_err());
+
+        assert!(PlaneLayout::new(128, 8, 0).is_err());
+        assert!(PlaneLayout::new(128, 8, 7).is_err());
+        assert!(PlaneLayout::new(u16::MAX, 8, u32::MAX / 1000).is_err());
+        assert!(PlaneLayout::new(u16::MAX, u16::MAX, u32::from(u16::MAX)).is_ok());
+        assert!(PlaneLayout::default()
+            .with_alphabets(['F', 'F'], ['L', 'R'])
+            .is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "FBFBBFFRL".parse::<Seat>().unwrap_err().to_string(),
+            "Expected a 10 character boarding pass, got 9 characters"
+        );
+        assert_eq!(
+            "FBFBBFFRLRR".parse::<Seat>().unwrap_err().to_string(),
+            "Expected a 10 character boarding pass, got 11 characters"
+        );
+        let err = parse_input("FBFBBFFRLR\nFBFBBFFRXR").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+        assert_eq!(
+            err.to_string().lines().next().unwrap(),
+            "day 5, line 2, column 9: Unexpected 'X', expected L or R"
+        );
+    }
+
+    #[test]
+    fn occupancy() {
+        let seats = |ids: &[u32]| -> Vec<Seat> {
+            ids.iter().map(|&id| Seat::from_id(id).unwrap()).collect()
+        };
+        let occupancy = Occupancy::new(&seats(&[17, 20, 18, 22, 20, 25]), &PlaneLayout::default());
+        assert_eq!(occupancy.missing, BTreeSet::from([19, 21, 23, 24]));
+        assert_eq!(occupancy.duplicates, vec![20]);
+        assert_eq!(occupancy.absent_front_rows, 0..2);
+        assert_eq!(occupancy.absent_back_rows, 4..128);
+
+        assert_eq!(part2(&seats(&[17, 18, 20, 20, 21])).unwrap(), 19);
+        assert!(part2(&seats(&[17, 18, 19])).is_err());
+        assert!(part2(&seats(&[17, 19, 21])).is_err());
+        assert!(part2(&[]).is_err());
+    }
+
+    #[test]
+    fn render() {
+        let layout = PlaneLayout::new(4, 4, 4).unwrap();
+        let seats: Vec<_> = [5, 6, 6, 8, 10]
+            .iter()
+            .map(|&id| layout.seat_from_id(id).unwrap())
+            .collect();
+        let occupancy = Occupancy::new(&seats, &layout);
+        assert_eq!(
+            occupancy.render_text(&layout, &[1]),
+            "  AB CD
+0 .. ..
+1 .# !o
+2 #o #.
+3 .. .."
+        );
+
+        let svg = occupancy.render_svg(&layout, &[1]);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 16);
+        assert!(svg.contains(
+            "<rect x=\"91\" y=\"41\" width=\"18\" height=\"18\" fill=\"#d0021b\"><title>FBRL (ID 6)</title></rect>"
+        ));
+    }
+
+    #[test]
+    fn render_edge_cases() {
+        let layout = PlaneLayout::new(0, 3, 3).unwrap();
+        assert_eq!(
+            Occupancy::new(&[], &layout).render_text(&layout, &[]),
+            "  ABC"
+        );
+
+        assert_eq!(column_label(25), "Z");
+        assert_eq!(column_label(26), "AA");
+        assert_eq!(column_label(27), "AB");
+        assert_eq!(column_label(26 * 27), "AAA");
+        let layout = PlaneLayout::new(1, 28, 28).unwrap();
+        let text = Occupancy::new(&[], &layout).render_text(&layout, &[]);
+        let header = text.lines().next().unwrap();
+        assert!(header.starts_with("    A  B"));
+        assert!(header.ends_with("  Z AA AB"));
+        assert_eq!(
+            text.lines().nth(1).unwrap(),
+            format!("0 {}", "  .".repeat(28))
+        );
+    }
 }
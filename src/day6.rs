@@ -1,52 +1,324 @@
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::BufRead,
+    str::FromStr,
+};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-struct Group {
-    n: u8,
-    counts: [u8; 26],
+use crate::error::{parse_blocks, read_blocks, ColumnError, ParseError};
+use crate::solution::Solution;
+
+/// The answers of a group of people to a set of yes/no questions.
+///
+/// Each question is identified by a single alphanumeric character. The
+/// common case of questions `a` to `z` is counted using a bitset per person,
+/// with any other identifiers kept in a map.
+#[derive(Debug, Default)]
+pub struct Group {
+    /// How many people answered each of questions `a` to `z`.
+    counts: [usize; 26],
+    /// How many people answered each question outside `a` to `z`.
+    other: BTreeMap<char, usize>,
+    /// Each person's answers.
+    people: Vec<Answers>,
 }
 
-impl Default for Group {
-    fn default() -> Self {
-        Group {
-            n: 0,
-            counts: [0; 26],
-        }
+/// The questions answered by a single person.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Answers {
+    /// Bitset of questions `a` to `z`.
+    letters: u32,
+    other: BTreeSet<char>,
+}
+
+impl Answers {
+    fn questions(&self) -> impl Iterator<Item = char> + '_ {
+        (0..26)
+            .filter(move |idx| self.letters >> idx & 1 == 1)
+            .map(|idx| char::from(b'a' + idx as u8))
+            .chain(self.other.iter().copied())
     }
 }
 
 impl Group {
     fn any(&self) -> u32 {
-        self.counts.iter().map(|x| if *x > 0 { 1 } else { 0 }).sum()
+        self.counts
+            .iter()
+            .map(|x| if *x > 0 { 1 } else { 0 })
+            .sum::<u32>()
+            + self.other.len() as u32
     }
 
     fn all(&self) -> u32 {
         self.counts
             .iter()
-            .map(|x| if *x == self.n { 1 } else { 0 })
+            .chain(self.other.values())
+            .map(|x| if *x == self.people.len() { 1 } else { 0 })
             .sum()
     }
+
+    /// Record one person's answers.
+    fn add_person(&mut self, answers: Answers) {
+        for (idx, count) in self.counts.iter_mut().enumerate() {
+            *count += (answers.letters >> idx & 1) as usize;
+        }
+        for c in &answers.other {
+            *self.other.entry(*c).or_insert(0) += 1;
+        }
+        self.people.push(answers);
+    }
+
+    /// The number of people who answered each question answered by anyone.
+    pub fn question_counts(&self) -> BTreeMap<char, usize> {
+        let mut counts = BTreeMap::new();
+        for person in &self.people {
+            for c in person.questions() {
+                *counts.entry(c).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
 }
 
 impl FromStr for Group {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        Ok(s.lines().fold(Group::default(), |mut group, line| {
-            group.n += 1;
-            line.bytes()
-                .map(|c| c as usize - 97)
-                .for_each(|idx| group.counts[idx] += 1);
+        let mut group = Group::default();
+        let mut offset = 0;
+        for line in s.split('\n') {
+            let mut answers = 0u32;
+            let mut other = BTreeSet::new();
+            for (i, c) in line.trim_end().char_indices() {
+                match c {
+                    'a'..='z' => answers |= 1 << (c as u32 - 'a' as u32),
+                    _ if c.is_alphanumeric() => {
+                        other.insert(c);
+                    }
+                    _ => {
+                        return Err(ColumnError::new(
+                            offset + i,
+                            anyhow!("Invalid question identifier {:?}", c),
+                        )
+                        .into())
+                    }
+                }
+            }
+            offset += line.len() + 1;
+            if answers != 0 || !other.is_empty() {
+                group.add_person(Answers {
+                    letters: answers,
+                    other,
+                });
+            }
+        }
+        Ok(group)
+    }
+}
+
+/// Parse groups from `reader` one at a time, without reading the whole input
+/// into memory first.
+pub fn read_groups(reader: impl BufRead) -> impl Iterator<Item = Result<Group>> {
+    read_blocks(6, reader)
+}
+
+/// The number of people answering each question, across every group.
+pub fn question_totals(groups: &[Group]) -> BTreeMap<char, usize> {
+    let mut totals = BTreeMap::new();
+    for group in groups {
+        for (c, count) in group.question_counts() {
+            *totals.entry(c).or_insert(0) += count;
+        }
+    }
+    totals
+}
+
+/// Every question which could be asked of `groups`: `a` to `z`, plus any
+/// other identifiers answered by anyone.
+pub fn universe(groups: &[Group]) -> BTreeSet<char> {
+    ('a'..='z')
+        .chain(groups.iter().flat_map(|g| g.other.keys().copied()))
+        .collect()
+}
+
+/// A set expression evaluated over the answers of each person in a group,
+/// giving a set of questions.
+///
+/// The syntax is:
+///
+/// - `any`: questions answered by anyone
+/// - `all`: questions answered by everyone
+/// - `none`: questions answered by nobody
+/// - `atleast(k)`, `exactly(k)`: questions answered by at least (or exactly)
+///   `k` people
+/// - `person(i)`: questions answered by the `i`th (0-based) person
+/// - `a | b`, `a & b`, `a - b`, `a ^ b`: union, intersection, difference and
+///   symmetric difference, evaluated left to right
+/// - `!a`: questions not in `a`
+/// - `(a)`: grouping
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    Any,
+    All,
+    None,
+    AtLeast(usize),
+    Exactly(usize),
+    Person(usize),
+    Union(Box<Query>, Box<Query>),
+    Intersection(Box<Query>, Box<Query>),
+    Difference(Box<Query>, Box<Query>),
+    SymmetricDifference(Box<Query>, Box<Query>),
+    Complement(Box<Query>),
+}
+
+impl Query {
+    /// Evaluate the query for `group`, where `universe` is every possible
+    /// question (see `universe`).
+    pub fn eval(&self, group: &Group, universe: &BTreeSet<char>) -> BTreeSet<char> {
+        let counted = |f: &dyn Fn(usize) -> bool| {
             group
-        }))
+                .question_counts()
+                .into_iter()
+                .filter(|(_, count)| f(*count))
+                .map(|(c, _)| c)
+                .collect()
+        };
+        let n = group.people.len();
+        match self {
+            Self::Any => counted(&|count| count > 0),
+            Self::All => counted(&|count| count == n),
+            Self::None => universe
+                .difference(&counted(&|count| count > 0))
+                .copied()
+                .collect(),
+            Self::AtLeast(k) => counted(&|count| count >= *k),
+            Self::Exactly(k) => counted(&|count| count == *k),
+            Self::Person(i) => group
+                .people
+                .get(*i)
+                .map_or_else(BTreeSet::new, |person| person.questions().collect()),
+            Self::Union(a, b) => &a.eval(group, universe) | &b.eval(group, universe),
+            Self::Intersection(a, b) => &a.eval(group, universe) & &b.eval(group, universe),
+            Self::Difference(a, b) => &a.eval(group, universe) - &b.eval(group, universe),
+            Self::SymmetricDifference(a, b) => &a.eval(group, universe) ^ &b.eval(group, universe),
+            Self::Complement(a) => universe - &a.eval(group, universe),
+        }
+    }
+}
+
+/// A recursive descent parser for `Query`.
+struct QueryParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.pos = self.s.len() - self.s[self.pos..].trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.s[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(c) => bail!("Expected {:?} at {}, found {:?}", expected, self.pos, c),
+            None => bail!(
+                "Expected {:?} at {}, found end of query",
+                expected,
+                self.pos
+            ),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Query> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.peek().filter(|c| "|&-^".contains(*c)) {
+            self.pos += 1;
+            let rhs = Box::new(self.term()?);
+            let lhs_box = Box::new(lhs);
+            lhs = match op {
+                '|' => Query::Union(lhs_box, rhs),
+                '&' => Query::Intersection(lhs_box, rhs),
+                '-' => Query::Difference(lhs_box, rhs),
+                _ => Query::SymmetricDifference(lhs_box, rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Query> {
+        match self.peek() {
+            Some('!') => {
+                self.pos += 1;
+                Ok(Query::Complement(Box::new(self.term()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            _ => {
+                let start = self.pos;
+                let len = self.s[start..]
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(self.s.len() - start);
+                self.pos += len;
+                match &self.s[start..self.pos] {
+                    "any" => Ok(Query::Any),
+                    "all" => Ok(Query::All),
+                    "none" => Ok(Query::None),
+                    "atleast" => Ok(Query::AtLeast(self.argument()?)),
+                    "exactly" => Ok(Query::Exactly(self.argument()?)),
+                    "person" => Ok(Query::Person(self.argument()?)),
+                    "" => bail!("Expected a query at {}", start),
+                    other => bail!("Unknown query {:?} at {}", other, start),
+                }
+            }
+        }
+    }
+
+    /// Parse a parenthesized number, like `(2)`.
+    fn argument(&mut self) -> Result<usize> {
+        self.expect('(')?;
+        self.skip_whitespace();
+        let start = self.pos;
+        let len = self.s[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.s.len() - start);
+        self.pos += len;
+        let n = self.s[start..self.pos]
+            .parse()
+            .with_context(|| format!("Expected a number at {}", start))?;
+        self.expect(')')?;
+        Ok(n)
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = QueryParser { s, pos: 0 };
+        let query = parser.expr()?;
+        if let Some(c) = parser.peek() {
+            bail!("Unexpected {:?} at {}", c, parser.pos);
+        }
+        Ok(query)
     }
 }
 
 #[aoc_generator(day6)]
-fn parse_input(input: &str) -> Vec<Group> {
-    input.split("\n\n").map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<Group>, ParseError> {
+    parse_blocks(6, input)
 }
 
 #[aoc(day6, part1)]
@@ -59,6 +331,26 @@ fn part2(input: &[Group]) -> u32 {
     input.iter().map(Group::all).sum()
 }
 
+pub struct Day6;
+
+impl Solution for Day6 {
+    const DAY: u8 = 6;
+    type Input = Vec<Group>;
+    type Output = u32;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part1(input))
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part2(input))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -67,8 +359,9 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "abc
+            part1(
+                &parse_input(
+                    "abc
 
 a
 b
@@ -83,7 +376,9 @@ a
 a
 
 b"
-            )),
+                )
+                .unwrap()
+            ),
             11
         );
     }
@@ -91,8 +386,9 @@ b"
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "abc
+            part2(
+                &parse_input(
+                    "abc
 
 a
 b
@@ -107,8 +403,91 @@ a
 a
 
 b"
-            )),
+                )
+                .unwrap()
+            ),
             6
         );
     }
+
+    #[test]
+    fn test_other_questions() {
+        let group: Group = "aAé1\r\nAé\r\néaa".parse().unwrap();
+        assert_eq!((group.any(), group.all()), (4, 1));
+
+        let err = parse_input("abc\n\nab\nc d").unwrap_err();
+        assert_eq!((err.line, err.column, err.text.as_str()), (4, 2, "c d"));
+    }
+
+    #[test]
+    fn test_query() {
+        let groups = parse_input("abc\nbcd\nce\n\nx\nxy").unwrap();
+        let universe = universe(&groups);
+        let eval = |query: &str, group: usize| {
+            query
+                .parse::<Query>()
+                .unwrap()
+                .eval(&groups[group], &universe)
+                .into_iter()
+                .collect::<String>()
+        };
+        assert_eq!(eval("any", 0), "abcde");
+        assert_eq!(eval("all", 0), "c");
+        assert_eq!(eval("exactly(1)", 0), "ade");
+        assert_eq!(eval("atleast( 2 )", 0), "bc");
+        assert_eq!(eval("person(0) ^ person(1)", 0), "ad");
+        assert_eq!(eval("any - (all | person(2))", 0), "abd");
+        assert_eq!(eval("none", 1).len(), 24);
+        assert_eq!(eval("!any & !none", 1), "");
+        assert_eq!(eval("person(5)", 1), "");
+
+        for invalid in &["", "anything", "any |", "atleast(x)", "(all", "all)"] {
+            assert!(invalid.parse::<Query>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_question_totals() {
+        let groups = parse_input("abc\nbcd\n\nb\nbA").unwrap();
+        assert_eq!(
+            question_totals(&groups),
+            vec![('A', 1), ('a', 1), ('b', 4), ('c', 2), ('d', 1)]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_large_group() {
+        let people = 3000;
+        let input = "ab\n".repeat(people) + "a";
+        let groups = parse_input(&input).unwrap();
+        assert_eq!(part1(&groups), 2);
+        assert_eq!(part2(&groups), 1);
+        assert_eq!(groups[0].question_counts()[&'a'], people + 1);
+        assert_eq!(groups[0].question_counts()[&'b'], people);
+    }
+
+    #[test]
+    fn test_read_groups() {
+        let input = "abc\r\nab\r\n\r\nb \t\r\n  \r\n\r\nc\n\n\n";
+        let groups = read_groups(input.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(part1(&groups), 3 + 1 + 1);
+        assert_eq!(part2(&groups), 2 + 1 + 1);
+
+        let err = read_groups("a\n\nb\nc?\n".as_bytes())
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "day 6, line 4, column 2: Invalid question identifier '?'
+  |
+4 | c?
+  |  ^"
+        );
+    }
 }
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    str::FromStr,
+};
 
 use anyhow::{Context, Error, Result};
 use once_cell::sync::Lazy;
@@ -7,32 +11,81 @@ use serde::Deserialize;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ParseError};
+use crate::solution::Solution;
+
 static RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?P<colour>.+) bags contain (?P<contents>.+)+.").unwrap());
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Hash)]
-struct BagColour(String);
+pub struct BagColour(String);
+
+impl From<&str> for BagColour {
+    fn from(colour: &str) -> Self {
+        Self(colour.to_string())
+    }
+}
+
+impl fmt::Display for BagColour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
 
 #[derive(Debug)]
-struct Bag {
+pub struct Bag {
     colour: BagColour,
     capacity: Option<Vec<BagCapacity>>,
 }
 
-impl Bag {
-    fn total_bags(&self, bags: &HashMap<BagColour, Bag>) -> usize {
-        // One for this bag, plus either 0 (if we don't hold any bags), or...
-        1 + self.capacity.as_ref().map_or(0, |inner| {
-            inner
-                .iter()
-                // ...for each bag inside, however many of that bag the current
-                // bag can hold, multiplied by that bag's capacity.
-                .map(|b| b.capacity * bags.get(&b.colour).unwrap().total_bags(&bags))
-                .sum::<usize>()
-        })
+/// A problem with a set of bag rules as a whole.
+#[derive(Debug, PartialEq)]
+pub enum RuleError {
+    /// More than one rule was given for a colour.
+    Duplicate(BagColour),
+    /// A rule says `container` bags hold `missing` bags, which have no rule.
+    Dangling {
+        container: BagColour,
+        missing: BagColour,
+    },
+    /// Each of these bags contains the next, and the last contains the first.
+    Cycle(Vec<BagColour>),
+}
+
+impl RuleError {
+    /// The colour of the bag whose rule should be pointed at when reporting
+    /// this error.
+    fn colour(&self) -> &BagColour {
+        match self {
+            Self::Duplicate(colour) => colour,
+            Self::Dangling { container, .. } => container,
+            Self::Cycle(cycle) => &cycle[0],
+        }
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate(colour) => write!(f, "Duplicate rule for {} bags", colour),
+            Self::Dangling { container, missing } => write!(
+                f,
+                "{} bags contain {} bags, which have no rule",
+                container, missing
+            ),
+            Self::Cycle(cycle) => {
+                write!(f, "Bags contain themselves: ")?;
+                for colour in cycle {
+                    write!(f, "{} -> ", colour)?;
+                }
+                write!(f, "{}", cycle[0])
+            }
+        }
     }
 }
 
+impl std::error::Error for RuleError {}
+
 impl FromStr for Bag {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
@@ -87,56 +140,364 @@ impl FromStr for BagCapacity {
     }
 }
 
-fn can_contain_bag<'a>(
-    desired: &'a BagColour,
-    current: &'a BagColour,
-    bags: &'a HashMap<BagColour, Bag>,
-    mut cache: &mut HashMap<&'a BagColour, bool>,
-) -> bool {
-    // Can't use `entry` or `raw_entry` APIs because we need to pass a mutable
-    // reference to the cache to this function, which wouldn't be allowed
-    // if we were currently holding such a reference.
-    if let Some(cached) = cache.get(current) {
-        *cached
-    } else {
-        let res = bags
-            .get(current)
+/// Which bags to include when exporting a `BagGraph`.
+#[derive(Clone, Debug)]
+pub enum Reach {
+    /// Every bag.
+    All,
+    /// The given bag and every bag it eventually contains.
+    From(BagColour),
+    /// The given bag and every bag which can eventually contain it.
+    To(BagColour),
+}
+
+/// Quote `s` as a DOT string.
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A valid set of bag rules, indexed in both directions so that containment
+/// queries can be answered for any colour.
+#[derive(Debug)]
+pub struct BagGraph {
+    bags: HashMap<BagColour, Bag>,
+    /// The colours of the bags which directly contain each colour.
+    contained_by: HashMap<BagColour, Vec<BagColour>>,
+    /// The number of bags inside a bag of each colour, or `None` if there
+    /// are too many to count.
+    contents: HashMap<BagColour, Option<usize>>,
+}
+
+impl BagGraph {
+    /// Index `rules`, checking that each colour has exactly one rule, that
+    /// every colour referred to has a rule, and that no bag (eventually)
+    /// contains itself.
+    pub fn new(rules: Vec<Bag>) -> Result<Self, RuleError> {
+        let mut bags = HashMap::with_capacity(rules.len());
+        for bag in rules {
+            if let Some(bag) = bags.insert(bag.colour.clone(), bag) {
+                return Err(RuleError::Duplicate(bag.colour));
+            }
+        }
+        let mut contained_by: HashMap<BagColour, Vec<BagColour>> = HashMap::new();
+        for bag in bags.values() {
+            for inner in bag.capacity.iter().flatten() {
+                if !bags.contains_key(&inner.colour) {
+                    return Err(RuleError::Dangling {
+                        container: bag.colour.clone(),
+                        missing: inner.colour.clone(),
+                    });
+                }
+                contained_by
+                    .entry(inner.colour.clone())
+                    .or_default()
+                    .push(bag.colour.clone());
+            }
+        }
+        let mut graph = Self {
+            bags,
+            contained_by,
+            contents: HashMap::new(),
+        };
+        if let Some(cycle) = graph.find_cycle() {
+            return Err(RuleError::Cycle(cycle.into_iter().cloned().collect()));
+        }
+        graph.contents = graph.count_contents();
+        Ok(graph)
+    }
+
+    /// Count the bags inside every bag, working up from the bags which
+    /// contain nothing so that each colour is only counted once.
+    fn count_contents(&self) -> HashMap<BagColour, Option<usize>> {
+        // The number of distinct colours inside each bag which haven't been
+        // counted yet.
+        let mut uncounted: HashMap<&BagColour, usize> = self
+            .bags
+            .keys()
+            .map(|colour| (colour, self.children(colour).collect::<HashSet<_>>().len()))
+            .collect();
+        let mut ready: Vec<&BagColour> = uncounted
+            .iter()
+            .filter(|(_, n)| **n == 0)
+            .map(|(colour, _)| *colour)
+            .collect();
+        let mut contents: HashMap<BagColour, Option<usize>> = HashMap::new();
+        while let Some(colour) = ready.pop() {
+            let total =
+                self.bags[colour]
+                    .capacity
+                    .iter()
+                    .flatten()
+                    .try_fold(0usize, |total, inner| {
+                        // Each inner bag, plus everything inside it.
+                        let each = contents[&inner.colour]?.checked_add(1)?;
+                        total.checked_add(inner.capacity.checked_mul(each)?)
+                    });
+            contents.insert(colour.clone(), total);
+            for parent in self.parents(colour).collect::<HashSet<_>>() {
+                let n = uncounted.get_mut(parent).unwrap();
+                *n -= 1;
+                if *n == 0 {
+                    ready.push(parent);
+                }
+            }
+        }
+        contents
+    }
+
+    /// The total number of bags inside a `colour` bag.
+    pub fn total_contents(&self, colour: &BagColour) -> Result<usize> {
+        self.contents
+            .get(colour)
+            .with_context(|| format!("No rule for {} bags", colour))?
+            .with_context(|| format!("Too many bags inside {} bags to count", colour))
+    }
+
+    /// Find a chain of bags where each contains the next, and the last
+    /// contains the first, if there is one.
+    ///
+    /// This is a depth-first search with an explicit stack, so that long
+    /// chains of rules can't overflow the call stack.
+    fn find_cycle(&self) -> Option<Vec<&BagColour>> {
+        // Colours whose descendants have all been checked.
+        let mut done = HashSet::new();
+        let mut colours: Vec<_> = self.bags.keys().collect();
+        // Check in a fixed order so the same cycle is always reported.
+        colours.sort_by_key(|c| &c.0);
+        for colour in colours {
+            if done.contains(colour) {
+                continue;
+            }
+            // The chain of bags being searched, the position of each in the
+            // chain, and the children of each still to search.
+            let mut path = vec![colour];
+            let mut on_path: HashMap<&BagColour, usize> = HashMap::new();
+            on_path.insert(colour, 0);
+            let mut unsearched = vec![self.children(colour)];
+            while let Some(children) = unsearched.last_mut() {
+                match children.next() {
+                    Some(child) => {
+                        if let Some(&start) = on_path.get(child) {
+                            return Some(path[start..].to_vec());
+                        }
+                        if !done.contains(child) {
+                            on_path.insert(child, path.len());
+                            path.push(child);
+                            unsearched.push(self.children(child));
+                        }
+                    }
+                    None => {
+                        unsearched.pop();
+                        let finished = path.pop().unwrap();
+                        on_path.remove(finished);
+                        done.insert(finished);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, colour: &BagColour) -> Option<&Bag> {
+        self.bags.get(colour)
+    }
+
+    /// The colours of the bags directly inside a `colour` bag.
+    fn children<'a>(&'a self, colour: &BagColour) -> impl Iterator<Item = &'a BagColour> {
+        self.bags
+            .get(colour)
             .and_then(|bag| bag.capacity.as_ref())
-            .map_or(false, |inner| {
-                inner.iter().any(|b| {
-                    &b.colour == desired || can_contain_bag(desired, &b.colour, bags, &mut cache)
-                })
-            });
-        cache.insert(current, res);
-        res
+            .into_iter()
+            .flatten()
+            .map(|inner| &inner.colour)
+    }
+
+    /// The colours of the bags directly containing a `colour` bag.
+    fn parents<'a>(&'a self, colour: &BagColour) -> impl Iterator<Item = &'a BagColour> {
+        self.contained_by.get(colour).into_iter().flatten()
+    }
+
+    /// Every colour reachable from `colour` by repeatedly following `next`,
+    /// not including `colour` itself (unless it's part of a cycle).
+    fn reachable<'a, I>(
+        &'a self,
+        colour: &BagColour,
+        next: impl Fn(&BagColour) -> I,
+    ) -> HashSet<&'a BagColour>
+    where
+        I: Iterator<Item = &'a BagColour>,
+    {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<_> = next(colour).collect();
+        while let Some(current) = stack.pop() {
+            if seen.insert(current) {
+                stack.extend(next(current));
+            }
+        }
+        seen
+    }
+
+    /// Every colour of bag which can eventually contain a `colour` bag.
+    pub fn ancestors(&self, colour: &BagColour) -> HashSet<&BagColour> {
+        self.reachable(colour, |c| self.parents(c))
+    }
+
+    /// Every colour of bag which a `colour` bag eventually contains.
+    pub fn descendants(&self, colour: &BagColour) -> HashSet<&BagColour> {
+        self.reachable(colour, |c| self.children(c))
+    }
+
+    /// Render the rules as a GraphViz DOT digraph, with an edge from each bag
+    /// to each bag it directly contains, labelled with how many it holds.
+    pub fn to_dot(&self, reach: &Reach) -> Result<String> {
+        let mut colours: Vec<&BagColour> = match reach {
+            Reach::All => self.bags.keys().collect(),
+            Reach::From(colour) | Reach::To(colour) => {
+                let (colour, _) = self
+                    .bags
+                    .get_key_value(colour)
+                    .with_context(|| format!("No rule for {} bags", colour))?;
+                let mut reachable = match reach {
+                    Reach::From(_) => self.descendants(colour),
+                    _ => self.ancestors(colour),
+                };
+                reachable.insert(colour);
+                reachable.into_iter().collect()
+            }
+        };
+        colours.sort_by_key(|c| &c.0);
+        let included: HashSet<_> = colours.iter().copied().collect();
+
+        let mut out = "digraph bags {\n".to_string();
+        for colour in &colours {
+            out.push_str(&format!("    {};\n", dot_quote(&colour.0)));
+        }
+        for colour in &colours {
+            for inner in self.bags[*colour].capacity.iter().flatten() {
+                if included.contains(&inner.colour) {
+                    out.push_str(&format!(
+                        "    {} -> {} [label=\"{}\"];\n",
+                        dot_quote(&colour.0),
+                        dot_quote(&inner.colour.0),
+                        inner.capacity
+                    ));
+                }
+            }
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    /// The shortest chain of bags from `outer` down to `inner`, each
+    /// directly containing the next, including both ends.
+    pub fn shortest_path(&self, outer: &BagColour, inner: &BagColour) -> Option<Vec<&BagColour>> {
+        let (outer, _) = self.bags.get_key_value(outer)?;
+        let mut previous: HashMap<&BagColour, &BagColour> = HashMap::new();
+        let mut queue = VecDeque::from(vec![outer]);
+        while let Some(current) = queue.pop_front() {
+            if current == inner {
+                let mut path = vec![current];
+                while let Some(prev) = previous.get(path[path.len() - 1]) {
+                    path.push(prev);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for child in self.children(current) {
+                if child != outer && !previous.contains_key(child) {
+                    previous.insert(child, current);
+                    queue.push_back(child);
+                }
+            }
+        }
+        None
+    }
+
+    /// The longest chain of bags from `outer` down to `inner`, each directly
+    /// containing the next, including both ends.
+    pub fn longest_path(&self, outer: &BagColour, inner: &BagColour) -> Option<Vec<&BagColour>> {
+        let (outer, _) = self.bags.get_key_value(outer)?;
+        // For each bag searched, the length of the longest chain from it
+        // down to `inner` and the next bag on that chain, or `None` if it
+        // can't reach `inner`. The rules have no cycles, so each bag's
+        // children are all searched before it's revisited.
+        let mut longest: HashMap<&BagColour, Option<(usize, Option<&BagColour>)>> = HashMap::new();
+        let mut stack = vec![(outer, false)];
+        while let Some((current, children_searched)) = stack.pop() {
+            if longest.contains_key(current) {
+                continue;
+            }
+            if current == inner {
+                longest.insert(current, Some((1, None)));
+            } else if children_searched {
+                let next = self
+                    .children(current)
+                    .filter_map(|child| longest[child].map(|(len, _)| (len, child)))
+                    .max_by_key(|(len, _)| *len);
+                longest.insert(current, next.map(|(len, child)| (len + 1, Some(child))));
+            } else {
+                stack.push((current, true));
+                stack.extend(
+                    self.children(current)
+                        .filter(|child| !longest.contains_key(child))
+                        .map(|child| (child, false)),
+                );
+            }
+        }
+        longest[outer]?;
+        let mut path = vec![outer];
+        while let Some((_, Some(next))) = longest[path[path.len() - 1]] {
+            path.push(next);
+        }
+        Some(path)
     }
 }
 
 #[aoc_generator(day7)]
-fn parse_input(input: &str) -> HashMap<BagColour, Bag> {
-    input
-        .lines()
-        .map(|el| {
-            let bag: Bag = el.parse().unwrap();
-            (bag.colour.clone(), bag)
-        })
-        .collect()
+fn parse_input(input: &str) -> Result<BagGraph, ParseError> {
+    let rules = parse_lines::<Bag>(7, input)?;
+    // Remember which line each rule is on, so that we can point at the
+    // offending rule if the rules are invalid.
+    let mut lines: HashMap<BagColour, Vec<usize>> = HashMap::new();
+    for (i, bag) in rules.iter().enumerate() {
+        lines.entry(bag.colour.clone()).or_default().push(i);
+    }
+    BagGraph::new(rules).map_err(|e| {
+        // Point at the second definition of a duplicate rule.
+        let i = *lines[e.colour()].last().unwrap();
+        let text = input.lines().nth(i).unwrap_or_default();
+        ParseError::new(7, i + 1, 1, text, e.into())
+    })
 }
 
 #[aoc(day7, part1)]
-fn part1(input: &HashMap<BagColour, Bag>) -> usize {
-    let desired = BagColour("shiny gold".to_string());
-    let mut cache = HashMap::new();
-    input
-        .keys()
-        .filter(|k| can_contain_bag(&desired, k, input, &mut cache))
-        .count()
+fn part1(input: &BagGraph) -> usize {
+    input.ancestors(&"shiny gold".into()).len()
 }
 
 #[aoc(day7, part2)]
-fn part2(input: &HashMap<BagColour, Bag>) -> usize {
-    let desired = BagColour("shiny gold".to_string());
-    input.get(&desired).unwrap().total_bags(&input) - 1
+fn part2(input: &BagGraph) -> Result<usize> {
+    input.total_contents(&"shiny gold".into())
+}
+
+pub struct Day7;
+
+impl Solution for Day7 {
+    const DAY: u8 = 7;
+    type Input = BagGraph;
+    type Output = usize;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        Ok(part1(input))
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
 }
 
 #[cfg(test)]
@@ -147,8 +508,9 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "light red bags contain 1 bright white bag, 2 muted yellow bags.
+            part1(
+                &parse_input(
+                    "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
 bright white bags contain 1 shiny gold bag.
 muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
@@ -157,7 +519,9 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags."
-            )),
+                )
+                .unwrap()
+            ),
             4
         );
     }
@@ -165,8 +529,9 @@ dotted black bags contain no other bags."
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "light red bags contain 1 bright white bag, 2 muted yellow bags.
+            part2(
+                &parse_input(
+                    "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
 bright white bags contain 1 shiny gold bag.
 muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
@@ -175,12 +540,16 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags."
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             32
         );
         assert_eq!(
-            part2(&parse_input(
-                "shiny gold bags contain 2 dark red bags.
+            part2(
+                &parse_input(
+                    "shiny gold bags contain 2 dark red bags.
 dark red bags contain 2 dark orange bags.
 dark orange bags contain 2 dark yellow bags.
 dark yellow bags contain 2 dark green bags.
@@ -188,8 +557,243 @@ dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.
 "
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             126
         );
     }
+
+    #[test]
+    fn test_graph_queries() {
+        let graph = parse_input(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.",
+        )
+        .unwrap();
+        let names = |colours: HashSet<&BagColour>| {
+            let mut names: Vec<_> = colours.into_iter().map(ToString::to_string).collect();
+            names.sort();
+            names
+        };
+        let path = |path: Option<Vec<&BagColour>>| {
+            path.map(|p| p.iter().map(ToString::to_string).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            names(graph.ancestors(&"faded blue".into())),
+            vec![
+                "bright white",
+                "dark olive",
+                "dark orange",
+                "light red",
+                "muted yellow",
+                "shiny gold",
+                "vibrant plum"
+            ]
+        );
+        assert!(graph.ancestors(&"light red".into()).is_empty());
+        assert_eq!(
+            names(graph.descendants(&"shiny gold".into())),
+            vec!["dark olive", "dotted black", "faded blue", "vibrant plum"]
+        );
+
+        assert_eq!(
+            path(graph.shortest_path(&"light red".into(), &"faded blue".into())),
+            Some(vec![
+                "light red".to_string(),
+                "muted yellow".to_string(),
+                "faded blue".to_string()
+            ])
+        );
+        assert_eq!(
+            path(graph.longest_path(&"light red".into(), &"faded blue".into()))
+                .unwrap()
+                .len(),
+            5
+        );
+        assert_eq!(
+            path(graph.shortest_path(&"shiny gold".into(), &"shiny gold".into())),
+            Some(vec!["shiny gold".to_string()])
+        );
+        assert_eq!(
+            graph.shortest_path(&"faded blue".into(), &"shiny gold".into()),
+            None
+        );
+        assert_eq!(
+            graph.longest_path(&"faded blue".into(), &"shiny gold".into()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let error = |input: &str| parse_input(input).unwrap_err().to_string();
+        assert_eq!(
+            error(
+                "light red bags contain 1 dark red bag.
+dark red bags contain no other bags.
+light red bags contain no other bags."
+            ),
+            "day 7, line 3, column 1: Duplicate rule for light red bags
+  |
+3 | light red bags contain no other bags.
+  | ^"
+        );
+        assert_eq!(
+            error(
+                "light red bags contain no other bags.
+dark red bags contain 2 light red bags, 1 plaid bag."
+            ),
+            "day 7, line 2, column 1: dark red bags contain plaid bags, which have no rule
+  |
+2 | dark red bags contain 2 light red bags, 1 plaid bag.
+  | ^"
+        );
+        assert_eq!(
+            error(
+                "light red bags contain 1 dark red bag.
+dark red bags contain 1 shiny gold bag, 1 faded blue bag.
+faded blue bags contain no other bags.
+shiny gold bags contain 3 light red bags."
+            ),
+            "day 7, line 2, column 1: Bags contain themselves: \
+dark red -> shiny gold -> light red -> dark red
+  |
+2 | dark red bags contain 1 shiny gold bag, 1 faded blue bag.
+  | ^"
+        );
+    }
+
+    /// Rules for a chain of `length` bags, each containing the next.
+    fn chain(length: usize) -> String {
+        let mut rules = String::new();
+        for i in 0..length - 1 {
+            rules.push_str(&format!(
+                "level {} bags contain 1 level {} bag.\n",
+                i,
+                i + 1
+            ));
+        }
+        rules.push_str(&format!("level {} bags contain no other bags.", length - 1));
+        rules
+    }
+
+    #[test]
+    fn test_deep_rules() {
+        let length = 50_000;
+        let graph = parse_input(&chain(length)).unwrap();
+        assert_eq!(graph.total_contents(&"level 0".into()).unwrap(), length - 1);
+        let last = BagColour(format!("level {}", length - 1));
+        assert_eq!(
+            graph.longest_path(&"level 0".into(), &last).unwrap().len(),
+            length
+        );
+
+        let cyclic = chain(length).replace(
+            &format!("level {} bags contain no other bags.", length - 1),
+            &format!("level {} bags contain 1 level 1 bag.", length - 1),
+        );
+        assert!(parse_input(&cyclic)
+            .unwrap_err()
+            .to_string()
+            .starts_with("day 7, line 2, column 1: Bags contain themselves: level 1 -> level 2"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let graph = parse_input(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 3 faded blue bags.
+faded blue bags contain no other bags.",
+        )
+        .unwrap();
+        assert_eq!(
+            graph.to_dot(&Reach::From("muted yellow".into())).unwrap(),
+            r#"digraph bags {
+    "faded blue";
+    "muted yellow";
+    "shiny gold";
+    "muted yellow" -> "shiny gold" [label="2"];
+    "muted yellow" -> "faded blue" [label="9"];
+    "shiny gold" -> "faded blue" [label="3"];
+}
+"#
+        );
+        assert_eq!(
+            graph.to_dot(&Reach::To("bright white".into())).unwrap(),
+            r#"digraph bags {
+    "bright white";
+    "light red";
+    "light red" -> "bright white" [label="1"];
+}
+"#
+        );
+        let all = graph.to_dot(&Reach::All).unwrap();
+        assert_eq!(all.matches(" -> ").count(), 6);
+        assert_eq!(
+            graph
+                .to_dot(&Reach::From("plaid".into()))
+                .unwrap_err()
+                .to_string(),
+            "No rule for plaid bags"
+        );
+        assert!(graph.to_dot(&Reach::To("plaid".into())).is_err());
+        assert_eq!(dot_quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+
+    #[test]
+    fn test_total_contents() {
+        // Each level holds two bags which both hold the next level, so
+        // counting without memoization would take 2^40 steps.
+        let depth = 40;
+        let mut rules = String::new();
+        for i in 0..depth {
+            rules.push_str(&format!(
+                "level {0} bags contain 1 left {1} bag, 1 right {1} bag.
+left {1} bags contain 1 level {1} bag.
+right {1} bags contain 1 level {1} bag.
+",
+                i,
+                i + 1
+            ));
+        }
+        rules.push_str(&format!("level {} bags contain no other bags.", depth));
+        let graph = parse_input(&rules).unwrap();
+        assert_eq!(
+            graph.total_contents(&"level 0".into()).unwrap(),
+            4 * ((1 << depth) - 1)
+        );
+        assert_eq!(graph.total_contents(&"left 40".into()).unwrap(), 1);
+
+        let mut rules = String::new();
+        for i in 0..30 {
+            rules.push_str(&format!(
+                "level {} bags contain 1000 level {} bags.\n",
+                i,
+                i + 1
+            ));
+        }
+        rules.push_str("level 30 bags contain no other bags.");
+        let graph = parse_input(&rules).unwrap();
+        assert_eq!(graph.total_contents(&"level 29".into()).unwrap(), 1000);
+        assert_eq!(
+            graph
+                .total_contents(&"level 0".into())
+                .unwrap_err()
+                .to_string(),
+            "Too many bags inside level 0 bags to count"
+        );
+        assert!(graph.total_contents(&"plaid".into()).is_err());
+    }
 }
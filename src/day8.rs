@@ -1,130 +1,173 @@
-use std::str::FromStr;
-
-use anyhow::{bail, Context, Error, Result};
+use anyhow::{bail, Context, Result};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-fn parse_int_with_leading_plus(s: &str) -> Result<i32> {
-    Ok(s.trim_start_matches('+').parse()?)
-}
+use crate::cfg::ControlFlowGraph;
+use crate::error::ParseError;
+use crate::solution::Solution;
+use crate::vm::{Instruction, Machine, Program, StepOutcome};
 
-#[derive(Clone, Copy, Debug)]
-enum Instruction {
-    Nop(i32),
-    Acc(i32),
-    Jmp(i32),
+#[aoc_generator(day8)]
+fn parse_input(input: &str) -> Result<Program, ParseError> {
+    input.parse()
 }
 
-impl Instruction {
-    fn swap_nop_jmp(&mut self) {
-        match self {
-            Instruction::Jmp(x) => *self = Instruction::Nop(*x),
-            Instruction::Nop(x) => *self = Instruction::Jmp(*x),
-            _ => unreachable!(),
-        };
+#[aoc(day8, part1)]
+fn part1(input: &Program) -> Result<i64> {
+    let mut machine = Machine::new(input);
+    match machine.run(input.len())? {
+        StepOutcome::LoopDetected { .. } => Ok(machine.acc()),
+        _ => bail!("Program halted without looping"),
     }
 }
 
-impl FromStr for Instruction {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        let mut iter = s.split(' ').rev();
-        let arg = iter
-            .next()
-            .context("No argument found!")
-            .and_then(parse_int_with_leading_plus)?;
-        match iter.next() {
-            Some("nop") => Ok(Self::Nop(arg)),
-            Some("acc") => Ok(Self::Acc(arg)),
-            Some("jmp") => Ok(Self::Jmp(arg)),
-            Some(instruction) => bail!("Invalid instruction found: {}", instruction),
-            None => bail!("No instruction found!"),
+/// A single `nop`/`jmp` swap which makes a program terminate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repair {
+    /// The index of the instruction to swap.
+    pub index: usize,
+    /// The accumulator once the repaired program terminates.
+    pub acc: i64,
+}
+
+/// Find the instruction to swap to make `program` terminate, in linear time.
+///
+/// First find every instruction from which the unmodified program
+/// terminates, by walking backwards from the address just past the end (and
+/// any `hlt`). Then run the program forwards: the first `nop` or `jmp` which
+/// would land in that set if swapped is the one to fix.
+///
+/// This assumes the program has no conditional jumps, so that its path
+/// doesn't depend on its registers.
+pub fn repair(program: &Program) -> Result<Repair> {
+    let instructions = program.instructions();
+    let len = instructions.len();
+    let in_bounds = |target: i64| (0..=len as i64).contains(&target);
+
+    // Which instructions lead directly to each address, including `len`.
+    let mut predecessors = vec![vec![]; len + 1];
+    for (pc, instruction) in instructions.iter().enumerate() {
+        for target in instruction.successors(pc as i64) {
+            if in_bounds(target) {
+                predecessors[target as usize].push(pc);
+            }
+        }
+    }
+    let mut terminates = vec![false; len + 1];
+    terminates[len] = true;
+    let mut stack = vec![len];
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if *instruction == Instruction::Hlt {
+            terminates[pc] = true;
+            stack.push(pc);
+        }
+    }
+    while let Some(pc) = stack.pop() {
+        for &prev in &predecessors[pc] {
+            if !terminates[prev] {
+                terminates[prev] = true;
+                stack.push(prev);
+            }
         }
     }
-}
 
-#[aoc_generator(day8)]
-fn parse_input(input: &str) -> Vec<Instruction> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+    let mut machine = Machine::new(program);
+    let index = loop {
+        let pc = machine.pc() as usize;
+        if let Some(instruction) = instructions.get(pc) {
+            let mut swapped = *instruction;
+            swapped.swap_nop_jmp();
+            if swapped != *instruction {
+                let target = swapped.successors(pc as i64)[0];
+                if in_bounds(target) && terminates[target as usize] {
+                    break pc;
+                }
+            }
+        }
+        match machine.step() {
+            StepOutcome::Continue => {}
+            outcome => bail!(
+                "No single nop/jmp swap makes the program terminate ({:?})",
+                outcome
+            ),
+        }
+    };
+
+    let mut repaired = program.clone();
+    repaired.instructions_mut()[index].swap_nop_jmp();
+    let mut machine = Machine::new(&repaired);
+    machine
+        .run(len)
+        .ok()
+        .filter(|outcome| *outcome == StepOutcome::Halted)
+        .context("Repaired program didn't terminate")?;
+    Ok(Repair {
+        index,
+        acc: machine.acc(),
+    })
 }
 
-#[derive(Debug)]
-struct State {
-    acc: i32,
-    hits: Vec<bool>,
-    instruction: i32,
+#[aoc(day8, part2)]
+fn part2(input: &Program) -> Result<i64> {
+    Ok(repair(input)?.acc)
 }
 
-impl State {
-    fn new(len: usize) -> Self {
-        Self {
-            acc: 0,
-            hits: vec![false; len],
-            instruction: 0,
-        }
+/// Find the swap from the program's control-flow graph, without running it.
+#[aoc(day8, part2, Static)]
+fn part2_static(input: &Program) -> Result<i64> {
+    match ControlFlowGraph::new(input)
+        .terminating_patches()?
+        .as_slice()
+    {
+        [patch] => Ok(patch.acc),
+        patches => bail!(
+            "Expected exactly one terminating patch, found {}",
+            patches.len()
+        ),
     }
+}
 
-    fn step(&mut self, instruction: Instruction) -> Option<i32> {
-        let idx = self.instruction as usize;
-        if self.hits[idx] {
-            return Some(self.acc);
+/// Try swapping each `nop` or `jmp` in turn, re-running the whole program
+/// each time. Quadratic, but kept to benchmark `repair` against.
+#[aoc(day8, part2, BruteForce)]
+fn part2_brute_force(input: &Program) -> Result<i64> {
+    let mut program = input.clone();
+    for idx in 0..program.len() {
+        if let Instruction::Acc(_) = program.instructions()[idx] {
+            continue;
         }
-        self.hits[idx] = true;
-        match instruction {
-            Instruction::Nop(_) => self.instruction += 1,
-            Instruction::Acc(x) => {
-                self.acc += x;
-                self.instruction += 1;
-            }
-            Instruction::Jmp(x) => self.instruction += x,
+        // Change the instruction back afterwards rather than cloning the
+        // entire program for every attempt.
+        program.instructions_mut()[idx].swap_nop_jmp();
+        let (outcome, acc) = {
+            let mut machine = Machine::new(&program);
+            (machine.run(program.len()), machine.acc())
+        };
+        program.instructions_mut()[idx].swap_nop_jmp();
+        if outcome == Ok(StepOutcome::Halted) {
+            return Ok(acc);
         }
-        None
     }
+    bail!("No single nop/jmp swap makes the program terminate")
 }
 
-#[aoc(day8, part1)]
-fn part1(input: &[Instruction]) -> i32 {
-    let mut state = State::new(input.len());
-    loop {
-        let instruction = input[state.instruction as usize];
-        if let Some(acc) = state.step(instruction) {
-            return acc;
-        }
+pub struct Day8;
+
+impl Solution for Day8 {
+    const DAY: u8 = 8;
+    type Input = Program;
+    type Output = i64;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
     }
-}
 
-#[aoc(day8, part2)]
-fn part2(input: &[Instruction]) -> i32 {
-    let len = input.len();
-    let mut input = input.to_vec();
-    let mut changed = 0;
-    loop {
-        // Get the index of the instruction to change.
-        // This lets us change the instruction back later to avoid cloning
-        // the entire set of instructions every iteration. (Otherwise we'd
-        // need a mutable reference which wouldn't let us access the input
-        // immutably later).
-        let to_change_idx = input
-            .iter()
-            .enumerate()
-            .filter(|(_i, x)| matches!(x, Instruction::Nop(_) | Instruction::Jmp(_)))
-            .nth(changed)
-            .unwrap()
-            .0;
-        input[to_change_idx].swap_nop_jmp();
-        changed += 1;
-
-        let mut state = State::new(len);
-        loop {
-            if state.instruction as usize == len {
-                return state.acc;
-            }
-            let instruction = input[state.instruction as usize];
-            if state.step(instruction).is_some() {
-                input[to_change_idx].swap_nop_jmp();
-                break;
-            }
-        }
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
     }
 }
 
@@ -136,8 +179,9 @@ mod tests {
     #[test]
     fn test_part1() {
         assert_eq!(
-            part1(&parse_input(
-                "nop +0
+            part1(
+                &parse_input(
+                    "nop +0
 acc +1
 jmp +4
 acc +3
@@ -146,7 +190,10 @@ acc -99
 acc +1
 jmp -4
 acc +6"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             5
         );
     }
@@ -154,8 +201,9 @@ acc +6"
     #[test]
     fn test_part2() {
         assert_eq!(
-            part2(&parse_input(
-                "nop +0
+            part2(
+                &parse_input(
+                    "nop +0
 acc +1
 jmp +4
 acc +3
@@ -164,8 +212,68 @@ acc -99
 acc +1
 jmp -4
 acc +6"
-            )),
+                )
+                .unwrap()
+            )
+            .unwrap(),
             8
         );
     }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse_input("nop +0\nacc +1\njmp x4").unwrap_err();
+        assert_eq!((err.line, err.column, err.text.as_str()), (3, 5, "jmp x4"));
+    }
+
+    #[test]
+    fn test_repair() {
+        let program = parse_input(
+            "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6",
+        )
+        .unwrap();
+        assert_eq!(repair(&program).unwrap(), Repair { index: 7, acc: 8 });
+        assert_eq!(part2_brute_force(&program).unwrap(), 8);
+        assert_eq!(part2_static(&program).unwrap(), 8);
+
+        // The first instruction jumps backwards out of bounds unless swapped.
+        let program = parse_input("jmp -1\nacc +2").unwrap();
+        assert_eq!(repair(&program).unwrap(), Repair { index: 0, acc: 2 });
+
+        assert!(repair(&parse_input("jmp +0\njmp -1").unwrap()).is_err());
+
+        let program = parse_input("add r0 +5\nnop +0\njmp -1").unwrap();
+        assert_eq!(repair(&program).unwrap(), Repair { index: 2, acc: 5 });
+        assert_eq!(part2_static(&program).unwrap(), 5);
+        assert!(part2_static(&parse_input("mul r0 +5\nnop +0\njmp -1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_repair_matches_brute_force() {
+        // A long program with a single jump back near the end, which needs
+        // turning into a `nop`.
+        let n = 2000;
+        let mut source = String::new();
+        for i in 0..n {
+            source.push_str(&format!("acc +{}\n", i % 7));
+            if i == n - 10 {
+                source.push_str("jmp -1000\n");
+            } else {
+                source.push_str("nop +0\n");
+            }
+        }
+        let program = parse_input(&source).unwrap();
+        let repaired = repair(&program).unwrap();
+        assert_eq!(repaired.index, 2 * (n - 10) + 1);
+        assert_eq!(repaired.acc, part2_brute_force(&program).unwrap());
+        assert_eq!(repaired.acc, part2_static(&program).unwrap());
+    }
 }
@@ -1,52 +1,83 @@
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ParseError};
+use crate::solution::Solution;
+
 #[aoc_generator(day9)]
-fn parse_input(input: &str) -> Vec<u64> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<u64>, ParseError> {
+    parse_lines(9, input)
 }
 
 fn last_is_not_sum(window: impl Iterator<Item = u64> + Clone, sum: u64) -> bool {
     !window.tuple_combinations().any(|(x, y)| x + y == sum)
 }
 
-fn first_rulebreaker(input: &[u64], preamble: usize) -> u64 {
+fn first_rulebreaker(input: &[u64], preamble: usize) -> Result<u64> {
     input
         .windows(preamble + 1)
         .find_map(|w| {
-            let last = *w.last().unwrap();
-            if last_is_not_sum(w.iter().take(preamble).cloned(), last) {
+            let (&last, preceding) = w.split_last()?;
+            if last_is_not_sum(preceding.iter().cloned(), last) {
                 Some(last)
             } else {
                 None
             }
         })
-        .unwrap()
+        .with_context(|| {
+            format!(
+                "Every number is the sum of two of the {} before it",
+                preamble
+            )
+        })
 }
 
 #[aoc(day9, part1)]
-fn part1(input: &[u64]) -> u64 {
+fn part1(input: &[u64]) -> Result<u64> {
     first_rulebreaker(input, 25)
 }
 
-fn sum_contiguous_numbers(input: &[u64], sum: u64) -> u64 {
-    for size in 2..(input.len() - 1) {
+fn sum_contiguous_numbers(input: &[u64], sum: u64) -> Result<u64> {
+    for size in 2..=input.len() {
         for window in input.windows(size) {
             if window.iter().sum::<u64>() == sum {
-                return window.iter().min().unwrap() + window.iter().max().unwrap();
+                let min = window.iter().min().unwrap();
+                let max = window.iter().max().unwrap();
+                return Ok(min + max);
             }
         }
     }
-    panic!("Could not find contiguous numbers summing to {}", sum)
+    bail!("Could not find contiguous numbers summing to {}", sum)
 }
 
 #[aoc(day9, part2)]
-fn part2(input: &[u64]) -> u64 {
-    let sum = first_rulebreaker(input, 25);
+fn part2(input: &[u64]) -> Result<u64> {
+    let sum = first_rulebreaker(input, 25)?;
     sum_contiguous_numbers(input, sum)
 }
 
+pub struct Day9;
+
+impl Solution for Day9 {
+    const DAY: u8 = 9;
+    type Input = Vec<u64>;
+    type Output = u64;
+
+    fn parse(input: &str) -> Result<Self::Input> {
+        Ok(parse_input(input)?)
+    }
+
+    fn part1(input: &Self::Input) -> Result<Self::Output> {
+        part1(input)
+    }
+
+    fn part2(input: &Self::Input) -> Result<Self::Output> {
+        part2(input)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -78,11 +109,14 @@ mod tests {
 309
 576
 ",
-                ),
+                )
+                .unwrap(),
                 5
-            ),
+            )
+            .unwrap(),
             127
         );
+        assert!(part1(&[1, 2]).is_err());
     }
 
     #[test]
@@ -109,10 +143,12 @@ mod tests {
 309
 576
 ",
-        );
+        )
+        .unwrap();
         assert_eq!(
-            sum_contiguous_numbers(input, first_rulebreaker(input, 5)),
+            sum_contiguous_numbers(input, first_rulebreaker(input, 5).unwrap()).unwrap(),
             62
-        )
+        );
+        assert!(sum_contiguous_numbers(&[1, 2, 3], 100).is_err());
     }
 }
@@ -0,0 +1,321 @@
+//! An interactive debugger for console programs (see `vm`).
+//!
+//! Commands:
+//!
+//! - `break N` / `b N`: stop before running the instruction at `N`
+//! - `delete N` / `d N`: remove the breakpoint at `N`
+//! - `step [N]` / `s [N]`: run `N` (default 1) instructions
+//! - `continue` / `c`: run until a breakpoint, a watched change, or the
+//!   program stops
+//! - `until-loop` / `u`: run until an instruction is about to run a second
+//!   time, ignoring breakpoints
+//! - `watch acc` / `unwatch acc`: stop whenever the accumulator changes
+//! - `print` / `p`: show the program counter, registers and next instruction
+//! - `trace [N]`: show the last `N` (default 10, at most `TRACE_LEN`)
+//!   instructions run
+//! - `help` / `h`: list commands
+//! - `quit` / `q`: exit
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, VecDeque},
+    convert::TryFrom,
+    rc::Rc,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::vm::{Machine, Program, Register, StepOutcome, TraceEntry, REGISTERS};
+
+/// The maximum number of instructions to run for a single `continue`,
+/// stopping programs which run forever.
+const CONTINUE_BUDGET: usize = 1_000_000;
+
+/// The number of instructions kept for `trace`, so that long runs don't
+/// keep every instruction in memory.
+const TRACE_LEN: usize = 1000;
+
+const HELP: &str = "break N, delete N, step [N], continue, until-loop, watch acc, \
+unwatch acc, print, trace [N], help, quit";
+
+/// What the debugger should do after a command.
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    /// Show this output and wait for the next command.
+    Output(String),
+    /// Exit the debugger.
+    Quit,
+}
+
+/// Why a run of instructions stopped.
+enum Stop {
+    Outcome(StepOutcome),
+    Breakpoint(usize),
+    AccChanged(i64, i64),
+    Loop(usize),
+    Budget,
+}
+
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    /// The last `TRACE_LEN` instructions run, oldest first, filled in by the
+    /// machine as it steps.
+    trace: Rc<RefCell<VecDeque<TraceEntry>>>,
+    breakpoints: BTreeSet<usize>,
+    watch_acc: bool,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let trace = Rc::new(RefCell::new(VecDeque::with_capacity(TRACE_LEN)));
+        let recorder = Rc::clone(&trace);
+        Self {
+            // Loops are left to `until-loop`, so that programs with
+            // conditional jumps can be debugged too.
+            machine: Machine::new(program)
+                .detect_loops(false)
+                .on_step(move |entry| {
+                    let mut trace = recorder.borrow_mut();
+                    if trace.len() == TRACE_LEN {
+                        trace.pop_front();
+                    }
+                    trace.push_back(*entry);
+                }),
+            trace,
+            breakpoints: BTreeSet::new(),
+            watch_acc: false,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    /// Run a single command, given as a line of input.
+    pub fn command(&mut self, line: &str) -> Result<Response> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |default: Option<usize>| -> Result<usize> {
+            match words.get(1) {
+                Some(word) => word
+                    .parse()
+                    .with_context(|| format!("Expected a number, got {}", word)),
+                None => default.with_context(|| format!("{} requires a number", words[0])),
+            }
+        };
+        let output = match words.first().copied() {
+            None => return Ok(Response::Output(String::new())),
+            Some("break") | Some("b") => {
+                let pc = number(None)?;
+                if pc >= self.machine.program().len() {
+                    bail!("No instruction at {}", pc);
+                }
+                self.breakpoints.insert(pc);
+                format!("Breakpoint at {}", pc)
+            }
+            Some("delete") | Some("d") => {
+                let pc = number(None)?;
+                if !self.breakpoints.remove(&pc) {
+                    bail!("No breakpoint at {}", pc);
+                }
+                format!("Deleted breakpoint at {}", pc)
+            }
+            Some("step") | Some("s") => {
+                let count = number(Some(1))?;
+                let start = self.machine.steps();
+                let stop = self.run(count, false, false);
+                let executed = self.last(self.machine.steps() - start);
+                match stop {
+                    Some(stop) => format!("{}{}", executed, self.describe(stop)),
+                    None => executed.trim_end().to_string(),
+                }
+            }
+            Some("continue") | Some("c") => {
+                let stop = self.run(CONTINUE_BUDGET, true, false);
+                self.describe(stop.unwrap_or(Stop::Budget))
+            }
+            Some("until-loop") | Some("u") => {
+                let stop = self.run(CONTINUE_BUDGET, false, true);
+                self.describe(stop.unwrap_or(Stop::Budget))
+            }
+            Some(command @ "watch") | Some(command @ "unwatch") => {
+                if words.get(1) != Some(&"acc") {
+                    bail!("Only acc can be watched");
+                }
+                self.watch_acc = command == "watch";
+                format!(
+                    "{} acc",
+                    if self.watch_acc {
+                        "Watching"
+                    } else {
+                        "Not watching"
+                    }
+                )
+            }
+            Some("print") | Some("p") => self.state(),
+            Some("trace") => {
+                let count = number(Some(10))?;
+                if count > TRACE_LEN {
+                    bail!("Only the last {} instructions are kept", TRACE_LEN);
+                }
+                self.last(count).trim_end().to_string()
+            }
+            Some("help") | Some("h") => HELP.to_string(),
+            Some("quit") | Some("q") => return Ok(Response::Quit),
+            Some(other) => bail!("Unknown command: {} (try help)", other),
+        };
+        Ok(Response::Output(output))
+    }
+
+    /// Run up to `count` instructions, returning why it stopped early, if it
+    /// did. The instruction at the current breakpoint (if any) always runs,
+    /// so that continuing from a breakpoint makes progress.
+    fn run(&mut self, count: usize, breakpoints: bool, until_loop: bool) -> Option<Stop> {
+        for i in 0..count {
+            let pc = self.machine.pc();
+            if pc >= 0 {
+                let pc = pc as usize;
+                if until_loop && self.machine.has_run(pc) {
+                    return Some(Stop::Loop(pc));
+                }
+                if breakpoints && i > 0 && self.breakpoints.contains(&pc) {
+                    return Some(Stop::Breakpoint(pc));
+                }
+            }
+            let acc = self.machine.acc();
+            match self.machine.step() {
+                StepOutcome::Continue => {}
+                outcome => return Some(Stop::Outcome(outcome)),
+            }
+            if self.watch_acc && self.machine.acc() != acc {
+                return Some(Stop::AccChanged(acc, self.machine.acc()));
+            }
+        }
+        None
+    }
+
+    /// The last `count` instructions run, one per line, or as many as are
+    /// kept.
+    fn last(&self, count: usize) -> String {
+        let trace = self.trace.borrow();
+        trace
+            .iter()
+            .skip(trace.len().saturating_sub(count))
+            .map(|entry| format!("{}\n", entry))
+            .collect()
+    }
+
+    fn describe(&self, stop: Stop) -> String {
+        let acc = self.machine.acc();
+        match stop {
+            Stop::Outcome(StepOutcome::Halted) => format!("Program halted, acc = {}", acc),
+            Stop::Outcome(StepOutcome::OutOfBounds { pc }) => {
+                format!("Jumped outside the program, to {}, acc = {}", pc, acc)
+            }
+            Stop::Outcome(outcome) => format!("Stopped: {:?}, acc = {}", outcome, acc),
+            Stop::Breakpoint(pc) => format!("Breakpoint at {}\n{}", pc, self.state()),
+            Stop::AccChanged(before, after) => {
+                format!("acc changed: {} -> {}\n{}", before, after, self.state())
+            }
+            Stop::Loop(pc) => format!(
+                "Instruction {} is about to run a second time, acc = {}",
+                pc, acc
+            ),
+            Stop::Budget => format!(
+                "Still running after {} instructions\n{}",
+                CONTINUE_BUDGET,
+                self.state()
+            ),
+        }
+    }
+
+    /// The program counter, registers, and next instruction.
+    fn state(&self) -> String {
+        let pc = self.machine.pc();
+        let next = usize::try_from(pc)
+            .ok()
+            .and_then(|pc| self.machine.program().instructions().get(pc))
+            .map_or_else(|| "(none)".to_string(), ToString::to_string);
+        let registers: Vec<String> = (0..REGISTERS)
+            .filter_map(Register::new)
+            .map(|r| format!("{}={}", r, self.machine.register(r)))
+            .collect();
+        format!("pc={} {}\nnext: {}", pc, registers.join(" "), next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn output(debugger: &mut Debugger, command: &str) -> String {
+        match debugger.command(command).unwrap() {
+            Response::Output(output) => output,
+            Response::Quit => panic!("Unexpected quit"),
+        }
+    }
+
+    const PROGRAM: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    fn test_debugger() {
+        let program: Program = PROGRAM.parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(
+            output(&mut debugger, "step 2"),
+            "    0  nop +0            acc 0 -> 0
+    1  acc +1            acc 0 -> 1"
+        );
+        assert_eq!(output(&mut debugger, "b 4"), "Breakpoint at 4");
+        assert_eq!(
+            output(&mut debugger, "c"),
+            "Breakpoint at 4
+pc=4 r0=5 r1=0 r2=0 r3=0 r4=0 r5=0 r6=0 r7=0
+next: jmp -3"
+        );
+        assert_eq!(output(&mut debugger, "d 4"), "Deleted breakpoint at 4");
+        assert_eq!(output(&mut debugger, "watch acc"), "Watching acc");
+        assert!(output(&mut debugger, "c").starts_with("acc changed: 5 -> 6\npc=2"));
+        assert_eq!(output(&mut debugger, "unwatch acc"), "Not watching acc");
+        assert_eq!(
+            output(&mut debugger, "until-loop"),
+            "Instruction 2 is about to run a second time, acc = 6"
+        );
+        assert_eq!(output(&mut debugger, "trace 1").lines().count(), 1);
+        assert_eq!(output(&mut debugger, "trace").lines().count(), 8);
+        assert!(debugger.command("trace 1001").is_err());
+
+        assert!(debugger.command("b 100").is_err());
+        assert!(debugger.command("d 3").is_err());
+        assert!(debugger.command("step x").is_err());
+        assert!(debugger.command("watch r1").is_err());
+        assert!(debugger.command("jump").is_err());
+        assert_eq!(debugger.command("quit").unwrap(), Response::Quit);
+    }
+
+    #[test]
+    fn test_debugger_halts() {
+        let program: Program = "acc +1\nhlt".parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(output(&mut debugger, "c"), "Program halted, acc = 1");
+        assert_eq!(output(&mut debugger, "s"), "Program halted, acc = 1");
+
+        let program: Program = "jmp +0".parse().unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert!(output(&mut debugger, "c").starts_with("Still running after 1000000"));
+        assert_eq!(
+            output(&mut debugger, &format!("trace {}", TRACE_LEN))
+                .lines()
+                .count(),
+            TRACE_LEN
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::{fmt, io::BufRead, str::FromStr};
+
+use anyhow::Error;
+
+/// An error parsing a puzzle input, pointing at the offending text.
+///
+/// The `Debug` implementation renders the same diagnostic as `Display`, so
+/// that the runner (which prints generator errors with `{:#?}`) shows
+/// something readable rather than a dump of the error's internals.
+pub struct ParseError {
+    /// The day whose input failed to parse.
+    pub day: u8,
+    /// The 1-based line number of the offending text.
+    pub line: usize,
+    /// The 1-based column of the offending text within `text`.
+    pub column: usize,
+    /// The full line containing the error.
+    pub text: String,
+    source: Error,
+}
+
+impl ParseError {
+    pub fn new(day: u8, line: usize, column: usize, text: &str, source: Error) -> Self {
+        Self {
+            day,
+            line,
+            column,
+            text: text.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(
+            f,
+            "day {}, line {}, column {}: {:#}",
+            self.day, self.line, self.column, self.source
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.text)?;
+        write!(f, "{} | {}^", gutter, " ".repeat(self.column - 1))
+    }
+}
+
+impl fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// An error found at a known position within the string being parsed.
+///
+/// `FromStr` implementations can return this (wrapped in an `anyhow::Error`)
+/// so that `parse_lines` and `parse_blocks` can point at the exact column,
+/// rather than the start of the line.
+#[derive(Debug)]
+pub struct ColumnError {
+    /// The 0-based byte offset of the error within the parsed string.
+    pub offset: usize,
+    source: Error,
+}
+
+impl ColumnError {
+    pub fn new(offset: usize, source: impl Into<Error>) -> Self {
+        Self {
+            offset,
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for ColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl std::error::Error for ColumnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.source()
+    }
+}
+
+/// Build a `ParseError` for `source`, raised while parsing `text`, which
+/// starts at (1-based) line `first_line` of the input.
+fn locate(day: u8, first_line: usize, text: &str, source: Error) -> ParseError {
+    let offset = source
+        .downcast_ref::<ColumnError>()
+        .map_or_else(|| text.len() - text.trim_start().len(), |e| e.offset);
+    // Find which line of `text` contains the offset.
+    let (line_idx, line_start, line) = text
+        .split('\n')
+        .enumerate()
+        .scan(0, |start, (i, line)| {
+            let this_start = *start;
+            *start += line.len() + 1;
+            Some((i, this_start, line))
+        })
+        .take_while(|&(i, start, _)| i == 0 || start <= offset)
+        .last()
+        .unwrap_or((0, 0, text));
+    let line = line.trim_end_matches('\r');
+    let column = line
+        .get(..offset.saturating_sub(line_start).min(line.len()))
+        .map_or(1, |before| before.chars().count() + 1);
+    ParseError::new(day, first_line + line_idx, column, line, source)
+}
+
+/// Parse each line of `input` as a `T`.
+pub fn parse_lines<T>(day: u8, input: &str) -> Result<Vec<T>, ParseError>
+where
+    T: FromStr,
+    T::Err: Into<Error>,
+{
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            line.parse()
+                .map_err(|e: T::Err| locate(day, i + 1, line, e.into()))
+        })
+        .collect()
+}
+
+/// Split `input` into blocks separated by blank lines, returning each block
+/// along with the (1-based) line number it starts on.
+///
+/// Lines containing only whitespace count as blank, and `\r\n` line endings
+/// are handled.
+pub fn blocks(input: &str) -> Vec<(usize, String)> {
+    Blocks::new(input.as_bytes())
+        .map(|block| block.expect("Reading from a string can't fail"))
+        .collect()
+}
+
+/// An iterator over the blocks of a reader, as split by `blocks`, reading a
+/// line at a time rather than holding the whole input in memory.
+pub struct Blocks<R> {
+    reader: R,
+    line: usize,
+}
+
+impl<R: BufRead> Blocks<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for Blocks<R> {
+    type Item = std::io::Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut current: Option<(usize, String)> = None;
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            match self.reader.read_line(&mut buf) {
+                Ok(0) => return current.map(Ok),
+                Ok(_) => self.line += 1,
+                Err(e) => return Some(Err(e)),
+            }
+            let line = buf.trim_end_matches(&['\r', '\n'][..]);
+            if line.trim().is_empty() {
+                if current.is_some() {
+                    return current.map(Ok);
+                }
+            } else {
+                let (_, block) = current.get_or_insert_with(|| (self.line, String::new()));
+                if !block.is_empty() {
+                    block.push('\n');
+                }
+                block.push_str(line);
+            }
+        }
+    }
+}
+
+/// Parse each block of `input`, as split by `blocks`, as a `T`.
+pub fn parse_blocks<T>(day: u8, input: &str) -> Result<Vec<T>, ParseError>
+where
+    T: FromStr,
+    T::Err: Into<Error>,
+{
+    blocks(input)
+        .into_iter()
+        .map(|(line, block)| {
+            block
+                .parse()
+                .map_err(|e: T::Err| locate(day, line, &block, e.into()))
+        })
+        .collect()
+}
+
+/// Parse each block of `reader`, as split by `blocks`, as a `T`, one block
+/// at a time.
+pub fn read_blocks<T, R>(day: u8, reader: R) -> impl Iterator<Item = Result<T, Error>>
+where
+    T: FromStr,
+    T::Err: Into<Error>,
+    R: BufRead,
+{
+    Blocks::new(reader).map(move |block| {
+        let (line, block) = block?;
+        block
+            .parse()
+            .map_err(|e: T::Err| locate(day, line, &block, e.into()).into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Digits;
+
+    impl FromStr for Digits {
+        type Err = Error;
+        fn from_str(s: &str) -> Result<Self, Error> {
+            match s.find(|c: char| !c.is_ascii_digit() && c != '\n') {
+                Some(offset) => Err(ColumnError::new(offset, anyhow!("Not a digit")).into()),
+                None => Ok(Self),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let err = parse_lines::<u32>(1, "12\n34\n5x6\n").unwrap_err();
+        assert_eq!((err.line, err.column, err.text.as_str()), (3, 1, "5x6"));
+
+        let err = parse_lines::<Digits>(1, "12\n34\n5x6\n").unwrap_err();
+        assert_eq!((err.line, err.column, err.text.as_str()), (3, 2, "5x6"));
+        assert_eq!(
+            err.to_string(),
+            "day 1, line 3, column 2: Not a digit
+  |
+3 | 5x6
+  |  ^"
+        );
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let input = "12\r\n34\r\n \r\n56\r\n7y\r\n\r\n\r\n8";
+        assert_eq!(
+            blocks(input),
+            vec![
+                (1, "12\n34".to_string()),
+                (4, "56\n7y".to_string()),
+                (8, "8".to_string())
+            ]
+        );
+        let err = parse_blocks::<Digits>(6, input).unwrap_err();
+        assert_eq!(
+            (err.day, err.line, err.column, err.text.as_str()),
+            (6, 5, 2, "7y")
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use aoc_runner_derive::aoc_lib;
 
+pub mod asm;
+pub mod cfg;
 pub mod day1;
 pub mod day10;
 pub mod day2;
@@ -10,5 +12,67 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+pub mod debugger;
+pub mod error;
+pub mod measurement;
+pub mod schema;
+pub mod solution;
+pub mod vm;
+
+pub use solution::{Entry, Solution};
+
+/// Every day solved so far, in order.
+pub const DAYS: &[Entry] = &[
+    Entry::new::<day1::Day1>(),
+    Entry::new::<day2::Day2>(),
+    Entry::new::<day3::Day3>(),
+    Entry::new::<day4::Day4>(),
+    Entry::new::<day5::Day5>(),
+    Entry::new::<day6::Day6>(),
+    Entry::new::<day7::Day7>(),
+    Entry::new::<day8::Day8>(),
+    Entry::new::<day9::Day9>(),
+    Entry::new::<day10::Day10>(),
+];
+
+/// Look up the registry entry for `day`.
+pub fn day(day: u8) -> Option<&'static Entry> {
+    DAYS.iter().find(|entry| entry.day == day)
+}
 
 aoc_lib! { year = 2020 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(
+            DAYS.iter().map(|entry| entry.day).collect::<Vec<_>>(),
+            (1..=10).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            day(1)
+                .unwrap()
+                .solve("1721\n979\n366\n299\n675\n1456")
+                .unwrap(),
+            ("514579".to_string(), "241861950".to_string())
+        );
+        assert_eq!(
+            day(8).unwrap().part1("nop +0\nacc +1\njmp -2").unwrap(),
+            "1"
+        );
+        assert!(day(2).unwrap().part1("1-3 a abcde").is_err());
+        assert!(day(2).unwrap().solve("0-3 a: abc").is_err());
+        assert!(day(5).unwrap().solve("").is_err());
+        assert!(day(9).unwrap().solve("1\n2").is_err());
+        assert!(day(3).unwrap().solve("").is_err());
+        assert!(day(3).unwrap().solve("\n").is_err());
+        assert!(day(10).unwrap().solve("").is_err());
+        assert!(day(10).unwrap().solve("1\n1\n2").is_err());
+        assert!(day(10).unwrap().solve("1\n10").is_err());
+        assert!(day(25).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use serde::{de, Deserialize, Deserializer};
+
+/// A unit of length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LengthUnit {
+    Millimetre,
+    Centimetre,
+    Metre,
+    Inch,
+    Foot,
+}
+
+impl LengthUnit {
+    /// Units in the order their symbols should be tried when parsing, so that
+    /// `m` doesn't swallow the end of `mm` or `cm`.
+    const ALL: [Self; 5] = [
+        Self::Millimetre,
+        Self::Centimetre,
+        Self::Metre,
+        Self::Inch,
+        Self::Foot,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Millimetre => "mm",
+            Self::Centimetre => "cm",
+            Self::Metre => "m",
+            Self::Inch => "in",
+            Self::Foot => "ft",
+        }
+    }
+
+    /// The number of micrometres in one of this unit.
+    pub fn micrometres(self) -> u64 {
+        match self {
+            Self::Millimetre => 1_000,
+            Self::Centimetre => 10_000,
+            Self::Metre => 1_000_000,
+            Self::Inch => 25_400,
+            Self::Foot => 304_800,
+        }
+    }
+}
+
+impl fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for LengthUnit {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|unit| unit.symbol() == s)
+            .with_context(|| format!("Unknown unit: {}", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for LengthUnit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A height (or any other length), stored in micrometres along with the unit
+/// it was given in.
+///
+/// Heights compare by their normalized length, so `1m == 100cm`.
+///
+/// Heights are parsed from a number followed by a unit symbol (`183cm`,
+/// `1.83m`, `1830mm`, `72in`, `6ft`), or from feet and inches (`5'11"`).
+#[derive(Clone, Copy, Debug)]
+pub struct Height {
+    micrometres: u64,
+    unit: LengthUnit,
+}
+
+impl Height {
+    pub fn new(value: f64, unit: LengthUnit) -> Self {
+        Self {
+            micrometres: (value * unit.micrometres() as f64).round() as u64,
+            unit,
+        }
+    }
+
+    /// The normalized length of this height.
+    pub fn micrometres(&self) -> u64 {
+        self.micrometres
+    }
+
+    /// The unit this height was given in.
+    pub fn unit(&self) -> LengthUnit {
+        self.unit
+    }
+
+    /// This height in its own unit.
+    pub fn value(&self) -> f64 {
+        self.to(self.unit)
+    }
+
+    /// This height in `unit`.
+    pub fn to(&self, unit: LengthUnit) -> f64 {
+        self.micrometres as f64 / unit.micrometres() as f64
+    }
+
+    /// The same height, expressed in `unit`.
+    pub fn convert(self, unit: LengthUnit) -> Self {
+        Self { unit, ..self }
+    }
+}
+
+impl PartialEq for Height {
+    fn eq(&self, other: &Self) -> bool {
+        self.micrometres == other.micrometres
+    }
+}
+
+impl Eq for Height {}
+
+impl PartialOrd for Height {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Height {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.micrometres.cmp(&other.micrometres)
+    }
+}
+
+/// Format `value` with at most three decimal places, dropping trailing zeros.
+fn format_decimal(value: f64) -> String {
+    let formatted = format!("{:.3}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.unit == LengthUnit::Foot {
+            let inches = self.to(LengthUnit::Inch);
+            let feet = (inches / 12.0).floor();
+            let inches = inches - feet * 12.0;
+            write!(f, "{}'", feet)?;
+            if format_decimal(inches) != "0" {
+                write!(f, "{}\"", format_decimal(inches))?;
+            }
+            Ok(())
+        } else {
+            write!(f, "{}{}", format_decimal(self.value()), self.unit)
+        }
+    }
+}
+
+/// Parse an unsigned decimal number, rejecting anything `f64::from_str` would
+/// otherwise accept, like signs, exponents and `inf`.
+fn parse_number(s: &str) -> Result<f64> {
+    let valid = !s.is_empty()
+        && s.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && s.matches('.').count() <= 1
+        && s.chars().any(|c| c.is_ascii_digit());
+    if !valid {
+        bail!("Invalid number: {}", s);
+    }
+    Ok(s.parse()?)
+}
+
+impl FromStr for Height {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let expected = || anyhow!("Expected a height like 183cm or 5'11\", got {}", s);
+        if let Some((feet, inches)) = s.find('\'').map(|i| (&s[..i], &s[i + 1..])) {
+            let feet = parse_number(feet).map_err(|_| expected())?;
+            let inches = match inches {
+                "" => 0.0,
+                _ => inches
+                    .strip_suffix('"')
+                    .ok_or_else(expected)
+                    .and_then(|x| parse_number(x).map_err(|_| expected()))?,
+            };
+            let micrometres = feet * LengthUnit::Foot.micrometres() as f64
+                + inches * LengthUnit::Inch.micrometres() as f64;
+            return Ok(Self {
+                micrometres: micrometres.round() as u64,
+                unit: LengthUnit::Foot,
+            });
+        }
+        let (number, unit) = LengthUnit::ALL
+            .iter()
+            .find_map(|unit| s.strip_suffix(unit.symbol()).map(|n| (n, *unit)))
+            .ok_or_else(expected)?;
+        Ok(Self::new(
+            parse_number(number).map_err(|_| expected())?,
+            unit,
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for Height {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn height(s: &str) -> Height {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(height("183cm").micrometres(), 1_830_000);
+        assert_eq!(height("1500cm").value(), 1500.0);
+        assert_eq!(height("6in").to(LengthUnit::Inch), 6.0);
+        assert_eq!(height("1.83m"), height("183cm"));
+        assert_eq!(height("1830mm"), height("1.83m"));
+        assert_eq!(height("5'11\""), height("71in"));
+        assert_eq!(height("6'"), height("6ft"));
+        assert_eq!(height("6ft"), height("72in"));
+        for invalid in &[
+            "", "c", "cm", "in", "-5cm", "1e3mm", "1.2.3m", "5'11", "183",
+        ] {
+            assert!(invalid.parse::<Height>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_convert_and_display() {
+        assert_eq!(height("183cm").to_string(), "183cm");
+        assert_eq!(
+            height("183cm").convert(LengthUnit::Metre).to_string(),
+            "1.83m"
+        );
+        assert_eq!(
+            height("71in").convert(LengthUnit::Foot).to_string(),
+            "5'11\""
+        );
+        assert_eq!(height("72in").convert(LengthUnit::Foot).to_string(), "6'");
+        assert_eq!(
+            height("76in").convert(LengthUnit::Centimetre).to_string(),
+            "193.04cm"
+        );
+        assert!(height("76in") > height("193cm"));
+        assert!(height("59in") < height("150cm"));
+    }
+}
@@ -0,0 +1,381 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{Context, Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::measurement::{Height, LengthUnit};
+
+/// Something with named string fields which can be checked against a
+/// `Schema`.
+pub trait Document {
+    fn field(&self, name: &str) -> Option<&str>;
+}
+
+impl Document for BTreeMap<String, String> {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.get(name).map(String::as_str)
+    }
+}
+
+/// A set of validation rules for the fields of a document, usually loaded
+/// from YAML:
+///
+/// ```yaml
+/// fields:
+///   - name: byr
+///     rule:
+///       type: year
+///       min: 1920
+///       max: 2002
+///   - name: cid
+///     required: false
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Schema {
+    pub fields: Vec<FieldSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    /// Whether the field must be present. Defaults to `true`.
+    #[serde(default = "FieldSpec::default_required")]
+    pub required: bool,
+    /// The rule the field's value must follow, if any.
+    pub rule: Option<Rule>,
+}
+
+impl FieldSpec {
+    fn default_required() -> bool {
+        true
+    }
+}
+
+/// An inclusive range of allowed values.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    /// A year between `min` and `max` inclusive.
+    Year { min: u16, max: u16 },
+    /// A length in one of the given units (see `Height`), within that unit's
+    /// range.
+    Measurement { units: BTreeMap<LengthUnit, Range> },
+    /// A length in any unit (see `Height`), between `min` and `max` once
+    /// converted to a common unit.
+    Height { min: Height, max: Height },
+    /// A `#` followed by lowercase hex digits.
+    HexColour,
+    /// One of a fixed set of values.
+    Enum { values: Vec<String> },
+    /// Exactly `length` decimal digits.
+    Digits { length: usize },
+    /// A string matching the whole of `pattern`.
+    Regex {
+        #[serde(deserialize_with = "deserialize_anchored_regex")]
+        pattern: Regex,
+    },
+}
+
+fn deserialize_anchored_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(serde::de::Error::custom)
+}
+
+/// Check that `value` is between `min` and `max` inclusive.
+fn check_range<T: PartialOrd + fmt::Display>(value: T, min: T, max: T) -> Result<(), String> {
+    if value < min {
+        Err(format!("{} is below min {}", value, min))
+    } else if value > max {
+        Err(format!("{} exceeds max {}", value, max))
+    } else {
+        Ok(())
+    }
+}
+
+impl Rule {
+    /// Check `value` against this rule, returning a description of the
+    /// problem if it fails.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            Self::Year { min, max } => {
+                let year: u16 = value
+                    .parse()
+                    .map_err(|_| format!("expected a year, got {}", value))?;
+                check_range(year, *min, *max)
+            }
+            Self::Measurement { units } => {
+                let expected = || {
+                    let names: Vec<_> = units.keys().map(|unit| unit.symbol()).collect();
+                    format!(
+                        "expected a measurement in {}, got {}",
+                        names.join(" or "),
+                        value
+                    )
+                };
+                let height: Height = value.parse().map_err(|_| expected())?;
+                let range = units.get(&height.unit()).ok_or_else(expected)?;
+                check_range(
+                    height,
+                    Height::new(range.min, height.unit()),
+                    Height::new(range.max, height.unit()),
+                )
+            }
+            Self::Height { min, max } => {
+                let height: Height = value
+                    .parse()
+                    .map_err(|_| format!("expected a height, got {}", value))?;
+                check_range(height, *min, *max)
+            }
+            Self::HexColour => {
+                if value.starts_with('#')
+                    && value
+                        .chars()
+                        .skip(1)
+                        .all(|c| (c.is_lowercase() || c.is_numeric()) && c.is_ascii_hexdigit())
+                {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "expected # followed by lowercase hex digits, got {}",
+                        value
+                    ))
+                }
+            }
+            Self::Enum { values } => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                } else {
+                    Err(format!("{} is not one of {}", value, values.join(", ")))
+                }
+            }
+            Self::Digits { length } => {
+                if !value.chars().all(|c| c.is_ascii_digit()) {
+                    Err(format!("expected {} digits, got {}", length, value))
+                } else if value.len() != *length {
+                    Err(format!("expected {} digits, got {}", length, value.len()))
+                } else {
+                    Ok(())
+                }
+            }
+            Self::Regex { pattern } => {
+                if pattern.is_match(value) {
+                    Ok(())
+                } else {
+                    Err(format!("{} does not match {}", value, pattern))
+                }
+            }
+        }
+    }
+}
+
+impl Schema {
+    /// Load a schema from a YAML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .with_context(|| format!("Could not read schema {}", path.display()))?
+            .parse()
+            .with_context(|| format!("Invalid schema {}", path.display()))
+    }
+
+    /// The names of the required fields missing from `document`.
+    pub fn missing_fields<'a>(&'a self, document: &impl Document) -> Vec<&'a str> {
+        self.fields
+            .iter()
+            .filter(|spec| spec.required && document.field(&spec.name).is_none())
+            .map(|spec| spec.name.as_str())
+            .collect()
+    }
+
+    /// Check every field of `document`, reporting each rule it breaks and
+    /// each required field it's missing.
+    pub fn validate(&self, document: &impl Document) -> ValidationReport {
+        let failures = self
+            .fields
+            .iter()
+            .filter_map(|spec| {
+                let message = match (document.field(&spec.name), &spec.rule) {
+                    (None, _) if spec.required => "missing".to_string(),
+                    (Some(value), Some(rule)) => rule.check(value).err()?,
+                    _ => return None,
+                };
+                Some(RuleFailure {
+                    field: spec.name.clone(),
+                    message,
+                })
+            })
+            .collect();
+        ValidationReport { failures }
+    }
+}
+
+impl FromStr for Schema {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(s)?)
+    }
+}
+
+/// A validation rule broken by a single field of a document.
+#[derive(Debug, PartialEq)]
+pub struct RuleFailure {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for RuleFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Every validation rule broken by a document.
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub failures: Vec<RuleFailure>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "valid");
+        }
+        for (i, failure) in self.failures.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const SCHEMA: &str = "
+fields:
+  - name: issued
+    rule:
+      type: year
+      min: 2000
+      max: 2020
+  - name: length
+    rule:
+      type: measurement
+      units:
+        m: { min: 1.5, max: 3 }
+        ft: { min: 5, max: 10 }
+  - name: height
+    rule:
+      type: height
+      min: 150cm
+      max: 6'4\"
+  - name: colour
+    required: false
+    rule:
+      type: hex_colour
+  - name: class
+    rule:
+      type: enum
+      values: [a, b]
+  - name: number
+    rule:
+      type: digits
+      length: 4
+  - name: code
+    rule:
+      type: regex
+      pattern: '[A-Z]{2}\\d'
+  - name: notes
+    required: false
+";
+
+    fn document(fields: &[(&str, &str)]) -> BTreeMap<String, String> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema: Schema = SCHEMA.parse().unwrap();
+        let valid = document(&[
+            ("issued", "2010"),
+            ("length", "1.75m"),
+            ("height", "6'4\""),
+            ("class", "a"),
+            ("number", "0123"),
+            ("code", "AB1"),
+        ]);
+        assert!(schema.validate(&valid).is_valid());
+
+        let invalid = document(&[
+            ("issued", "1999"),
+            ("length", "200cm"),
+            ("height", "1.94m"),
+            ("colour", "#zzz"),
+            ("number", "123"),
+            ("code", "AB12"),
+        ]);
+        assert_eq!(schema.missing_fields(&invalid), vec!["class"]);
+        assert_eq!(
+            schema.validate(&invalid).to_string(),
+            "issued: 1999 is below min 2000
+length: expected a measurement in m or ft, got 200cm
+height: 1.94m exceeds max 6'4\"
+colour: expected # followed by lowercase hex digits, got #zzz
+class: missing
+number: expected 4 digits, got 3
+code: AB12 does not match ^(?:[A-Z]{2}\\d)$"
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("schema-{}.yaml", std::process::id()));
+        fs::write(&path, SCHEMA).unwrap();
+        let schema = Schema::load(&path);
+        fs::write(&path, "fields: 3").unwrap();
+        let invalid = Schema::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(schema.unwrap().fields.len(), 8);
+        assert!(invalid
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid schema"));
+        assert!(Schema::load(dir.join("no-such-schema.yaml"))
+            .unwrap_err()
+            .to_string()
+            .starts_with("Could not read schema"));
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        assert!("fields: [{ name: a, rule: { type: nope } }]"
+            .parse::<Schema>()
+            .is_err());
+        assert!("fields: [{ name: a, rule: { type: regex, pattern: '(' } }]"
+            .parse::<Schema>()
+            .is_err());
+    }
+}
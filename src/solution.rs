@@ -0,0 +1,69 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+
+/// A solution to a single day's puzzle.
+///
+/// This mirrors the `aoc_generator`/`aoc` functions in each day's module, but
+/// can be called directly rather than only through the generated runner.
+pub trait Solution {
+    /// The day this solves.
+    const DAY: u8;
+
+    /// The parsed puzzle input.
+    type Input;
+    /// The answer to each part.
+    type Output: Display;
+
+    fn parse(input: &str) -> Result<Self::Input>;
+    fn part1(input: &Self::Input) -> Result<Self::Output>;
+    fn part2(input: &Self::Input) -> Result<Self::Output>;
+}
+
+/// A type-erased `Solution`, as listed in the crate's `DAYS` registry.
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub day: u8,
+    part1: fn(&str) -> Result<String>,
+    part2: fn(&str) -> Result<String>,
+    solve: fn(&str) -> Result<(String, String)>,
+}
+
+impl Entry {
+    pub const fn new<S: Solution>() -> Self {
+        Self {
+            day: S::DAY,
+            part1: part1::<S>,
+            part2: part2::<S>,
+            solve: solve::<S>,
+        }
+    }
+
+    /// Parse `input` and solve part 1.
+    pub fn part1(&self, input: &str) -> Result<String> {
+        (self.part1)(input)
+    }
+
+    /// Parse `input` and solve part 2.
+    pub fn part2(&self, input: &str) -> Result<String> {
+        (self.part2)(input)
+    }
+
+    /// Parse `input` once and solve both parts.
+    pub fn solve(&self, input: &str) -> Result<(String, String)> {
+        (self.solve)(input)
+    }
+}
+
+fn part1<S: Solution>(input: &str) -> Result<String> {
+    Ok(S::part1(&S::parse(input)?)?.to_string())
+}
+
+fn part2<S: Solution>(input: &str) -> Result<String> {
+    Ok(S::part2(&S::parse(input)?)?.to_string())
+}
+
+fn solve<S: Solution>(input: &str) -> Result<(String, String)> {
+    let input = S::parse(input)?;
+    Ok((S::part1(&input)?.to_string(), S::part2(&input)?.to_string()))
+}
@@ -4,9 +4,11 @@ use anyhow::{Error, Result};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::error::{parse_lines, ParseError};
+
 #[aoc_generator(day2)]
-fn parse_input(input: &str) -> Vec<u32> {
-    input.lines().map(|el| el.parse().unwrap()).collect()
+fn parse_input(input: &str) -> Result<Vec<u32>, ParseError> {
+    parse_lines(2, input)
 }
 
 #[aoc(day2, part1)]
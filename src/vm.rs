@@ -0,0 +1,820 @@
+//! A virtual machine for the handheld game console's boot code (day 8).
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::error::{parse_lines, ColumnError, ParseError};
+
+pub(crate) fn parse_int_with_leading_plus(s: &str) -> Result<i32> {
+    Ok(s.trim_start_matches('+').parse()?)
+}
+
+/// Split `s` on whitespace, returning each word with its byte offset.
+pub(crate) fn words(s: &str) -> Vec<(usize, &str)> {
+    s.split_whitespace()
+        .map(|word| (word.as_ptr() as usize - s.as_ptr() as usize, word))
+        .collect()
+}
+
+/// Whether `word` could be a label: a letter or underscore followed by
+/// letters, digits and underscores.
+pub(crate) fn is_label(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The number of registers in a machine.
+pub const REGISTERS: usize = 8;
+
+/// One of a machine's registers, written `r0` to `r7`. Register `r0` is the
+/// accumulator, and can also be written `acc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Register(u8);
+
+impl Register {
+    pub const ACC: Self = Self(0);
+
+    pub fn new(index: usize) -> Option<Self> {
+        if index < REGISTERS {
+            Some(Self(index as u8))
+        } else {
+            None
+        }
+    }
+
+    pub fn index(self) -> usize {
+        usize::from(self.0)
+    }
+}
+
+impl FromStr for Register {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "acc" {
+            return Ok(Self::ACC);
+        }
+        s.strip_prefix('r')
+            .and_then(|n| n.parse().ok())
+            .and_then(Self::new)
+            .with_context(|| format!("Expected a register (r0 to r{}), got {}", REGISTERS - 1, s))
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+/// An instruction argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i32),
+}
+
+impl Operand {
+    // The opcode table checks operand kinds before building instructions, so
+    // these can't fail for operands passed to `OpcodeSpec::build`.
+
+    fn register(self) -> Register {
+        match self {
+            Self::Register(r) => r,
+            Self::Immediate(_) => unreachable!("checked by the opcode table"),
+        }
+    }
+
+    fn immediate(self) -> i32 {
+        match self {
+            Self::Immediate(x) => x,
+            Self::Register(_) => unreachable!("checked by the opcode table"),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Register(r) => r.fmt(f),
+            Self::Immediate(x) => write!(f, "{:+}", x),
+        }
+    }
+}
+
+/// What an instruction accepts for one of its operands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperandKind {
+    /// A register.
+    Register,
+    /// A number.
+    Immediate,
+    /// A jump offset: a number, or (in assembly) a label.
+    Offset,
+    /// A register or a number.
+    Value,
+}
+
+impl OperandKind {
+    /// Parse `word` as an operand of this kind, using `label` to resolve
+    /// offsets which aren't numbers.
+    fn parse(self, word: &str, label: &dyn Fn(&str) -> Result<i32>) -> Result<Operand> {
+        match self {
+            Self::Register => Ok(Operand::Register(word.parse()?)),
+            Self::Immediate => Ok(Operand::Immediate(parse_int_with_leading_plus(word)?)),
+            Self::Offset if is_label(word) => Ok(Operand::Immediate(label(word)?)),
+            Self::Offset => Ok(Operand::Immediate(parse_int_with_leading_plus(word)?)),
+            Self::Value => match word.parse() {
+                Ok(register) => Ok(Operand::Register(register)),
+                Err(_) => parse_int_with_leading_plus(word)
+                    .map(Operand::Immediate)
+                    .with_context(|| format!("Expected a register or number, got {}", word)),
+            },
+        }
+    }
+}
+
+/// An entry in the opcode table: an instruction's mnemonic and operands,
+/// and how to build it.
+pub struct OpcodeSpec {
+    pub name: &'static str,
+    pub operands: &'static [OperandKind],
+    build: fn(&[Operand]) -> Instruction,
+}
+
+impl OpcodeSpec {
+    /// Build the instruction from operands of the kinds in `self.operands`.
+    fn build(&self, operands: &[Operand]) -> Instruction {
+        (self.build)(operands)
+    }
+}
+
+/// Every instruction the machine understands. To add one, add an
+/// `Instruction` variant, an entry here, and its behaviour to
+/// `Machine::step`.
+pub static OPCODES: &[OpcodeSpec] = {
+    use OperandKind::*;
+    &[
+        OpcodeSpec {
+            name: "nop",
+            operands: &[Offset],
+            build: |ops| Instruction::Nop(ops[0].immediate()),
+        },
+        OpcodeSpec {
+            name: "acc",
+            operands: &[Immediate],
+            build: |ops| Instruction::Acc(ops[0].immediate()),
+        },
+        OpcodeSpec {
+            name: "jmp",
+            operands: &[Offset],
+            build: |ops| Instruction::Jmp(ops[0].immediate()),
+        },
+        OpcodeSpec {
+            name: "jz",
+            operands: &[Register, Offset],
+            build: |ops| Instruction::Jz(ops[0].register(), ops[1].immediate()),
+        },
+        OpcodeSpec {
+            name: "jnz",
+            operands: &[Register, Offset],
+            build: |ops| Instruction::Jnz(ops[0].register(), ops[1].immediate()),
+        },
+        OpcodeSpec {
+            name: "add",
+            operands: &[Register, Value],
+            build: |ops| Instruction::Add(ops[0].register(), ops[1]),
+        },
+        OpcodeSpec {
+            name: "mul",
+            operands: &[Register, Value],
+            build: |ops| Instruction::Mul(ops[0].register(), ops[1]),
+        },
+        OpcodeSpec {
+            name: "out",
+            operands: &[Value],
+            build: |ops| Instruction::Out(ops[0]),
+        },
+        OpcodeSpec {
+            name: "hlt",
+            operands: &[],
+            build: |_| Instruction::Hlt,
+        },
+    ]
+};
+
+/// Look up the opcode table entry for the mnemonic `name`.
+pub fn opcode(name: &str) -> Option<&'static OpcodeSpec> {
+    OPCODES.iter().find(|spec| spec.name == name)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// Do nothing.
+    Nop(i32),
+    /// Add to the accumulator.
+    Acc(i32),
+    /// Jump by an offset.
+    Jmp(i32),
+    /// Jump by an offset if a register is zero.
+    Jz(Register, i32),
+    /// Jump by an offset if a register isn't zero.
+    Jnz(Register, i32),
+    /// Add a value to a register.
+    Add(Register, Operand),
+    /// Multiply a register by a value.
+    Mul(Register, Operand),
+    /// Output a value.
+    Out(Operand),
+    /// Stop the program.
+    Hlt,
+}
+
+impl Instruction {
+    pub fn opcode(self) -> &'static str {
+        match self {
+            Self::Nop(_) => "nop",
+            Self::Acc(_) => "acc",
+            Self::Jmp(_) => "jmp",
+            Self::Jz(..) => "jz",
+            Self::Jnz(..) => "jnz",
+            Self::Add(..) => "add",
+            Self::Mul(..) => "mul",
+            Self::Out(_) => "out",
+            Self::Hlt => "hlt",
+        }
+    }
+
+    pub fn operands(self) -> Vec<Operand> {
+        use Operand::*;
+        match self {
+            Self::Nop(x) | Self::Acc(x) | Self::Jmp(x) => vec![Immediate(x)],
+            Self::Jz(r, x) | Self::Jnz(r, x) => vec![Register(r), Immediate(x)],
+            Self::Add(r, v) | Self::Mul(r, v) => vec![Register(r), v],
+            Self::Out(v) => vec![v],
+            Self::Hlt => vec![],
+        }
+    }
+
+    /// The offset this instruction may jump by, if it's a jump.
+    pub fn jump_offset(self) -> Option<i32> {
+        match self {
+            Self::Jmp(x) | Self::Jz(_, x) | Self::Jnz(_, x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// The addresses which could be run after running this instruction at
+    /// `pc`.
+    pub fn successors(self, pc: i64) -> Vec<i64> {
+        match self {
+            Self::Jmp(x) => vec![pc + i64::from(x)],
+            Self::Jz(_, x) | Self::Jnz(_, x) => vec![pc + 1, pc + i64::from(x)],
+            Self::Hlt => vec![],
+            _ => vec![pc + 1],
+        }
+    }
+
+    /// Turn a `nop` into a `jmp` or vice versa, leaving anything else alone.
+    pub fn swap_nop_jmp(&mut self) {
+        match self {
+            Instruction::Jmp(x) => *self = Instruction::Nop(*x),
+            Instruction::Nop(x) => *self = Instruction::Jmp(*x),
+            _ => {}
+        };
+    }
+}
+
+/// Parse an instruction from its words (as split by `words`), using `label`
+/// to resolve any jump offsets which aren't numbers.
+pub(crate) fn parse_words(
+    words: &[(usize, &str)],
+    label: &dyn Fn(&str) -> Result<i32>,
+) -> Result<Instruction, ColumnError> {
+    let (opcode_offset, name) = *words
+        .first()
+        .ok_or_else(|| ColumnError::new(0, anyhow!("No instruction found!")))?;
+    let spec = opcode(name).ok_or_else(|| {
+        ColumnError::new(
+            opcode_offset,
+            anyhow!("Invalid instruction found: {}", name),
+        )
+    })?;
+    let args = &words[1..];
+    if let Some((offset, word)) = args.get(spec.operands.len()) {
+        return Err(ColumnError::new(*offset, anyhow!("Unexpected {:?}", word)));
+    }
+    if args.len() < spec.operands.len() {
+        let message = match args.len() {
+            0 => anyhow!("No argument found!"),
+            n => anyhow!(
+                "{} takes {} arguments, got {}",
+                name,
+                spec.operands.len(),
+                n
+            ),
+        };
+        return Err(ColumnError::new(opcode_offset, message));
+    }
+    let operands = spec
+        .operands
+        .iter()
+        .zip(args)
+        .map(|(kind, (offset, word))| {
+            kind.parse(word, label)
+                .map_err(|e| ColumnError::new(*offset, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(spec.build(&operands))
+}
+
+impl FromStr for Instruction {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(parse_words(&words(s), &|word| {
+            bail!("Expected a number, got {}", word)
+        })?)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.opcode())?;
+        for operand in self.operands() {
+            write!(f, " {}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// A program for the console, one instruction per line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Self { instructions }
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn instructions_mut(&mut self) -> &mut [Instruction] {
+        &mut self.instructions
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        parse_lines(8, s).map(Self::new)
+    }
+}
+
+/// The result of executing a single step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Continue,
+    /// The program counter is just past the last instruction, or at a `hlt`,
+    /// so the program has terminated normally.
+    Halted,
+    /// The instruction at `pc` has already been executed, so (unless loop
+    /// detection is turned off, see `Machine::detect_loops`) the program
+    /// would run forever. The instruction is not executed again.
+    LoopDetected { pc: usize },
+    /// The program counter points outside the program (other than just past
+    /// the end).
+    OutOfBounds { pc: i64 },
+}
+
+/// A reason a program couldn't be run to completion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VmError {
+    /// A jump took the program counter outside the program.
+    OutOfBounds { pc: i64 },
+    /// The program ran for `steps` steps without halting or looping.
+    BudgetExhausted { steps: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { pc } => write!(f, "Jumped outside the program, to {}", pc),
+            Self::BudgetExhausted { steps } => {
+                write!(f, "Program still running after {} steps", steps)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// A record of a single executed instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub acc_before: i64,
+    pub acc_after: i64,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}  {:<16}  acc {} -> {}",
+            self.pc,
+            self.instruction.to_string(),
+            self.acc_before,
+            self.acc_after
+        )
+    }
+}
+
+/// A function called with each instruction as it's executed.
+struct StepCallback<'a>(Box<dyn FnMut(&TraceEntry) + 'a>);
+
+impl fmt::Debug for StepCallback<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StepCallback")
+    }
+}
+
+/// The state of a console running a program.
+#[derive(Debug)]
+pub struct Machine<'a> {
+    program: &'a Program,
+    /// The index of the next instruction to run. Signed, since a jump can
+    /// take it below zero.
+    pc: i64,
+    registers: [i64; REGISTERS],
+    /// Every value output so far.
+    output: Vec<i64>,
+    /// Which instructions have been executed.
+    visited: Vec<bool>,
+    detect_loops: bool,
+    /// Every instruction executed so far, in order, if tracing.
+    trace: Option<Vec<TraceEntry>>,
+    on_step: Option<StepCallback<'a>>,
+    steps: usize,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Self {
+            program,
+            pc: 0,
+            registers: [0; REGISTERS],
+            output: vec![],
+            visited: vec![false; program.len()],
+            detect_loops: true,
+            trace: None,
+            on_step: None,
+            steps: 0,
+        }
+    }
+
+    /// Whether to record every instruction executed (see `trace`). Off by
+    /// default.
+    pub fn record_trace(mut self, record: bool) -> Self {
+        self.trace = if record { Some(vec![]) } else { None };
+        self
+    }
+
+    /// Call `callback` with each instruction as it's executed, for example
+    /// to stream a trace to a file without keeping it in memory.
+    pub fn on_step(mut self, callback: impl FnMut(&TraceEntry) + 'a) -> Self {
+        self.on_step = Some(StepCallback(Box::new(callback)));
+        self
+    }
+
+    /// Every instruction executed so far, in order, if recording a trace.
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the instruction at `pc` has been executed.
+    pub fn has_run(&self, pc: usize) -> bool {
+        self.visited.get(pc).copied().unwrap_or(false)
+    }
+
+    pub fn program(&self) -> &'a Program {
+        self.program
+    }
+
+    /// Whether to stop with `StepOutcome::LoopDetected` when an instruction
+    /// is about to run a second time. On by default, since without
+    /// conditional jumps that means the program would run forever.
+    pub fn detect_loops(mut self, detect_loops: bool) -> Self {
+        self.detect_loops = detect_loops;
+        self
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.pc
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.register(Register::ACC)
+    }
+
+    pub fn register(&self, register: Register) -> i64 {
+        self.registers[register.index()]
+    }
+
+    pub fn output(&self) -> &[i64] {
+        &self.output
+    }
+
+    fn value(&self, operand: Operand) -> i64 {
+        match operand {
+            Operand::Register(r) => self.register(r),
+            Operand::Immediate(x) => i64::from(x),
+        }
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Why the next instruction can't be executed, if it can't.
+    fn stopped(&self) -> Option<StepOutcome> {
+        let len = self.program.len() as i64;
+        if self.pc == len {
+            return Some(StepOutcome::Halted);
+        }
+        if self.pc < 0 || self.pc > len {
+            return Some(StepOutcome::OutOfBounds { pc: self.pc });
+        }
+        let pc = self.pc as usize;
+        if self.program.instructions[pc] == Instruction::Hlt {
+            return Some(StepOutcome::Halted);
+        }
+        if self.detect_loops && self.visited[pc] {
+            return Some(StepOutcome::LoopDetected { pc });
+        }
+        None
+    }
+
+    /// Execute the next instruction, unless the program has halted, looped
+    /// or jumped out of bounds.
+    pub fn step(&mut self) -> StepOutcome {
+        if let Some(outcome) = self.stopped() {
+            return outcome;
+        }
+        let pc = self.pc as usize;
+        let instruction = self.program.instructions[pc];
+        self.visited[pc] = true;
+        self.steps += 1;
+        let acc_before = self.acc();
+        // Arithmetic wraps rather than panicking on overflow.
+        let mut next = self.pc + 1;
+        match instruction {
+            Instruction::Nop(_) | Instruction::Hlt => {}
+            Instruction::Acc(x) => {
+                self.registers[0] = self.registers[0].wrapping_add(i64::from(x));
+            }
+            Instruction::Jmp(x) => next = self.pc + i64::from(x),
+            Instruction::Jz(r, x) => {
+                if self.register(r) == 0 {
+                    next = self.pc + i64::from(x);
+                }
+            }
+            Instruction::Jnz(r, x) => {
+                if self.register(r) != 0 {
+                    next = self.pc + i64::from(x);
+                }
+            }
+            Instruction::Add(r, v) => {
+                self.registers[r.index()] = self.register(r).wrapping_add(self.value(v));
+            }
+            Instruction::Mul(r, v) => {
+                self.registers[r.index()] = self.register(r).wrapping_mul(self.value(v));
+            }
+            Instruction::Out(v) => self.output.push(self.value(v)),
+        }
+        self.pc = next;
+        let acc_after = self.acc();
+        if self.trace.is_some() || self.on_step.is_some() {
+            let entry = TraceEntry {
+                pc,
+                instruction,
+                acc_before,
+                acc_after,
+            };
+            if let Some(StepCallback(callback)) = &mut self.on_step {
+                callback(&entry);
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.push(entry);
+            }
+        }
+        StepOutcome::Continue
+    }
+
+    /// Run until the program halts or (if detecting loops) an instruction is
+    /// about to be executed a second time, returning `StepOutcome::Halted` or
+    /// `StepOutcome::LoopDetected`.
+    ///
+    /// Fails if the program jumps out of bounds, or would need to execute
+    /// more than `budget` instructions. A program which stops after exactly
+    /// `budget` instructions succeeds, so a budget of the program's length
+    /// is enough to run any program without conditional jumps to a halt or
+    /// a loop.
+    pub fn run(&mut self, budget: usize) -> Result<StepOutcome, VmError> {
+        let mut remaining = budget;
+        loop {
+            match self.stopped() {
+                None if remaining == 0 => {
+                    return Err(VmError::BudgetExhausted { steps: self.steps })
+                }
+                None => {
+                    self.step();
+                    remaining -= 1;
+                }
+                Some(StepOutcome::OutOfBounds { pc }) => return Err(VmError::OutOfBounds { pc }),
+                Some(outcome) => return Ok(outcome),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn program(s: &str) -> Program {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_step() {
+        let program = program("nop +0\nacc +1\njmp -2");
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.step(), StepOutcome::Continue);
+        assert_eq!(machine.step(), StepOutcome::Continue);
+        assert_eq!((machine.pc(), machine.acc()), (2, 1));
+        assert_eq!(machine.step(), StepOutcome::Continue);
+        assert_eq!(machine.step(), StepOutcome::LoopDetected { pc: 0 });
+        assert_eq!(machine.step(), StepOutcome::LoopDetected { pc: 0 });
+        assert_eq!(machine.steps(), 3);
+    }
+
+    #[test]
+    fn test_run() {
+        let halts = program("acc +3\njmp +2\nacc +100\nacc -1");
+        let mut machine = Machine::new(&halts);
+        assert_eq!(machine.run(10), Ok(StepOutcome::Halted));
+        assert_eq!(machine.acc(), 2);
+
+        // It halts after executing exactly three instructions.
+        let mut machine = Machine::new(&halts);
+        assert_eq!(machine.run(2), Err(VmError::BudgetExhausted { steps: 2 }));
+        assert_eq!(machine.steps(), 2);
+        assert_eq!(Machine::new(&halts).run(3), Ok(StepOutcome::Halted));
+
+        let loops = program("acc +1\njmp -1");
+        let mut machine = Machine::new(&loops);
+        assert_eq!(machine.run(2), Ok(StepOutcome::LoopDetected { pc: 0 }));
+        assert_eq!(machine.acc(), 1);
+        assert_eq!(
+            Machine::new(&loops).run(1),
+            Err(VmError::BudgetExhausted { steps: 1 })
+        );
+        assert_eq!(
+            Machine::new(&loops).run(0),
+            Err(VmError::BudgetExhausted { steps: 0 })
+        );
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        for (source, pc) in &[("nop +0\njmp -2", -1), ("jmp +3\nnop +0", 3)] {
+            let program = program(source);
+            let mut machine = Machine::new(&program);
+            assert_eq!(machine.run(10), Err(VmError::OutOfBounds { pc: *pc }));
+            assert_eq!(machine.step(), StepOutcome::OutOfBounds { pc: *pc });
+        }
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!(
+            "jmp\t  -3 ".parse::<Instruction>().unwrap(),
+            Instruction::Jmp(-3)
+        );
+        assert_eq!("acc 4".parse::<Instruction>().unwrap(), Instruction::Acc(4));
+        assert_eq!(Instruction::Acc(4).to_string(), "acc +4");
+        assert_eq!(Instruction::Nop(0).to_string(), "nop +0");
+        for invalid in &["", "nop", "div +2", "jmp +1 +2", "acc x"] {
+            assert!(invalid.parse::<Instruction>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_extended_instructions() {
+        let source = "add r1 +5
+add r2 +1
+mul r2 r1
+add r1 -1
+jnz r1 -2
+out r2
+jz r1 +2
+out -1
+hlt
+out +99";
+        let program = program(source);
+        let mut machine = Machine::new(&program).detect_loops(false);
+        assert_eq!(machine.run(100), Ok(StepOutcome::Halted));
+        assert_eq!(machine.output(), &[120]);
+        assert_eq!(machine.pc(), 8);
+        assert_eq!(machine.register("r2".parse().unwrap()), 120);
+        assert_eq!(machine.step(), StepOutcome::Halted);
+
+        // The same program stops at the first repeated instruction if loops
+        // are detected.
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(100), Ok(StepOutcome::LoopDetected { pc: 2 }));
+
+        assert_eq!(
+            program
+                .instructions()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            source
+        );
+        assert_eq!(
+            "add acc r7".parse::<Instruction>().unwrap(),
+            Instruction::Add(Register::ACC, Operand::Register(Register(7)))
+        );
+        for invalid in &[
+            "add r8 +1",
+            "add +1 +1",
+            "jz r1",
+            "out",
+            "hlt +1",
+            "jnz r1 r2",
+        ] {
+            assert!(invalid.parse::<Instruction>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let program = program("nop +0\nacc +1\njmp -2");
+        let mut machine = Machine::new(&program).record_trace(true);
+        machine.run(10).unwrap();
+        assert_eq!(
+            machine.trace(),
+            &[
+                TraceEntry {
+                    pc: 0,
+                    instruction: Instruction::Nop(0),
+                    acc_before: 0,
+                    acc_after: 0
+                },
+                TraceEntry {
+                    pc: 1,
+                    instruction: Instruction::Acc(1),
+                    acc_before: 0,
+                    acc_after: 1
+                },
+                TraceEntry {
+                    pc: 2,
+                    instruction: Instruction::Jmp(-2),
+                    acc_before: 1,
+                    acc_after: 1
+                },
+            ]
+        );
+        assert_eq!(
+            machine.trace()[1].to_string(),
+            "    1  acc +1            acc 0 -> 1"
+        );
+        assert!(Machine::new(&program).trace().is_empty());
+
+        let mut streamed = vec![];
+        let mut machine = Machine::new(&program).on_step(|entry| streamed.push(entry.pc));
+        machine.run(10).unwrap();
+        assert!(machine.trace().is_empty());
+        drop(machine);
+        assert_eq!(streamed, vec![0, 1, 2]);
+    }
+}